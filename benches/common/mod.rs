@@ -0,0 +1,40 @@
+//! Helpers shared by the benches, for timing two implementations against each
+//! other. The inputs come from `aoc_2022::util::random`.
+
+// Each bench only uses some of these
+#![allow(dead_code)]
+
+use std::{
+    fmt::Debug,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Best time out of `runs`, with the result.
+pub fn time<T>(runs: u32, f: impl Fn() -> T) -> (T, Duration) {
+    let mut result = None;
+    let mut best = Duration::MAX;
+    for _ in 0..runs {
+        let start = Instant::now();
+        result = Some(black_box(f()));
+        best = best.min(start.elapsed());
+    }
+    (result.expect("no runs"), best)
+}
+
+/// Times both implementations, checks that they agree and prints how much
+/// faster the first one is.
+pub fn compare<T: PartialEq + Debug>(
+    name: &str,
+    runs: u32,
+    (label, f): (&str, impl Fn() -> T),
+    (other_label, g): (&str, impl Fn() -> T),
+) {
+    let (a, time_a) = time(runs, f);
+    let (b, time_b) = time(runs, g);
+    assert_eq!(a, b, "{name}: {label} and {other_label} disagree");
+    println!(
+        "  {name}: {label} {time_a:>12?}, {other_label} {time_b:>12?} ({:.1}x)",
+        time_b.as_secs_f64() / time_a.as_secs_f64()
+    );
+}
@@ -0,0 +1,52 @@
+//! Compares the dense and hash set backends for day 18 on generated droplets,
+//! much larger than the real input.
+//!
+//! Run with `cargo bench --bench day18`.
+
+mod common;
+
+use std::fmt::Write;
+
+use aoc_2022::{
+    days::day18::{self, Cube},
+    util::random::Random,
+};
+use common::compare;
+
+/// Noisy ball of lava, with random holes that become air pockets.
+fn generate(radius: isize, seed: u64) -> String {
+    let mut random = Random::new(seed);
+    let mut out = String::new();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let dist = x * x + y * y + z * z;
+                let jitter = random.below(radius as u64 * 4) as isize;
+                if dist <= radius * radius - jitter && random.below(10) != 0 {
+                    writeln!(out, "{},{},{}", x + radius, y + radius, z + radius).unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+fn main() {
+    type Solver = fn(&[Cube]) -> usize;
+    let solvers: [(&str, Solver, Solver); 2] = [
+        ("part 1", day18::part1, day18::part1_hash_set),
+        ("part 2", day18::part2, day18::part2_hash_set),
+    ];
+    for radius in [10, 25, 50] {
+        let input = day18::parse(&generate(radius, 0x2022_1218));
+        println!("radius {radius}, {} cubes", input.len());
+        for (name, dense, hash_set) in solvers {
+            compare(
+                name,
+                5,
+                ("bit grid", || dense(&input)),
+                ("hash set", || hash_set(&input)),
+            );
+        }
+    }
+}
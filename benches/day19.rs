@@ -0,0 +1,56 @@
+//! Compares the next-robot search for day 19 with the minute-by-minute search
+//! over explored states, on generated blueprints.
+//!
+//! Run with `cargo bench --bench day19`.
+
+mod common;
+
+use std::fmt::Write;
+
+use aoc_2022::{
+    days::day19::{self, Recipe},
+    util::random::Random,
+};
+use common::compare;
+
+/// Blueprints with costs in the same ranges as the real inputs.
+fn generate(count: usize, seed: u64) -> String {
+    let mut random = Random::new(seed);
+    let mut out = String::new();
+    for i in 1..=count {
+        writeln!(
+            out,
+            "Blueprint {i}: Each ore robot costs {} ore. Each clay robot costs {} ore. \
+             Each obsidian robot costs {} ore and {} clay. \
+             Each geode robot costs {} ore and {} obsidian.",
+            random.range(2, 4),
+            random.range(2, 4),
+            random.range(2, 4),
+            random.range(5, 20),
+            random.range(2, 4),
+            random.range(7, 20),
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn main() {
+    type Solver = fn(&[Recipe]) -> i32;
+    let solvers: [(&str, Solver, Solver); 2] = [
+        ("part 1", day19::part1, day19::part1_explored),
+        ("part 2", day19::part2, day19::part2_explored),
+    ];
+    for seed in [0x2022_1219, 0xdead_beef] {
+        let input = day19::parse(&generate(30, seed));
+        println!("seed {seed:#x}, {} blueprints", input.len());
+        for (name, next_robot, explored) in solvers {
+            compare(
+                name,
+                3,
+                ("next robot", || next_robot(&input)),
+                ("explored set", || explored(&input)),
+            );
+        }
+    }
+}
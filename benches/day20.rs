@@ -0,0 +1,42 @@
+//! Compares mixing with the blocked list for day 20 with rotating a `VecDeque`,
+//! on generated inputs.
+//!
+//! Run with `cargo bench --bench day20`.
+
+mod common;
+
+use aoc_2022::{days::day20, util::random::Random};
+use common::compare;
+
+/// Numbers in the same range as the real inputs, with a single 0.
+fn generate(count: usize, seed: u64) -> Vec<i32> {
+    let mut random = Random::new(seed);
+    let mut numbers = (1..count)
+        .map(|_| random.range(-10000, 10000) as i32)
+        .map(|n| if n == 0 { 1 } else { n })
+        .collect::<Vec<_>>();
+    numbers.insert(count / 2, 0);
+    numbers
+}
+
+fn main() {
+    type Solver = fn(&[i32]) -> i64;
+    let solvers: [(&str, Solver, Solver); 2] = [
+        ("part 1", day20::part1, |input| {
+            day20::part1_vec_deque(input) as i64
+        }),
+        ("part 2", day20::part2, day20::part2_vec_deque),
+    ];
+    for (count, seed) in [(5000, 0x2022_1220), (20000, 0xdead_beef)] {
+        let input = generate(count, seed);
+        println!("seed {seed:#x}, {} numbers", input.len());
+        for (name, blocked, deque) in solvers {
+            compare(
+                name,
+                3,
+                ("blocked list", || blocked(&input)),
+                ("VecDeque", || deque(&input)),
+            );
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Compares walking the flat day 22 map with precomputed wrap targets against
+//! searching the row ranges on every step, on generated maps.
+//!
+//! Run with `cargo bench --bench day22`.
+
+mod common;
+
+use std::fmt::Write;
+
+use aoc_2022::{
+    days::day22::{self, Grid},
+    util::random::Random,
+};
+use common::compare;
+
+/// A map with the same net as the real inputs, random walls and directions.
+fn generate(size: usize, moves: usize, seed: u64) -> String {
+    let mut random = Random::new(seed);
+    //  AB
+    //  C
+    // ED
+    // F
+    let net: [&[usize]; 4] = [&[1, 2], &[1], &[0, 1], &[0]];
+    let mut out = String::new();
+    for faces in net {
+        for _ in 0..size {
+            let width = (faces.last().unwrap() + 1) * size;
+            let line = (0..width)
+                .map(|x| {
+                    if !faces.contains(&(x / size)) {
+                        ' '
+                    } else if random.below(10) == 0 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            writeln!(out, "{line}").unwrap();
+        }
+    }
+    // The walk starts on the first tile of the first row
+    out.replace_range(size..size + 1, ".");
+    out.push('\n');
+    for _ in 0..moves {
+        let turn = if random.below(2) == 0 { 'L' } else { 'R' };
+        write!(out, "{}{turn}", random.below(3 * size as u64) + 1).unwrap();
+    }
+    write!(out, "{}", random.below(3 * size as u64) + 1).unwrap();
+    out
+}
+
+fn main() {
+    type Solver = fn(&Grid) -> isize;
+    let (dense, ranges): (Solver, Solver) = (day22::part1, day22::part1_ranges);
+    for (size, seed) in [(50, 0x2022_1222), (200, 0xdead_beef)] {
+        let input = day22::parse(&generate(size, 2000, seed));
+        println!("seed {seed:#x}, faces of {size}x{size} tiles");
+        compare(
+            "part 1",
+            5,
+            ("dense map", || dense(&input)),
+            ("row ranges", || ranges(&input)),
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Compares the row bitmask simulation for day 23 with the hash set one, on
+//! generated inputs.
+//!
+//! Run with `cargo bench --bench day23`.
+
+mod common;
+
+use aoc_2022::{
+    days::day23::{self, Grid},
+    util::random::Random,
+};
+use common::compare;
+
+/// A square of elves with the same size and density as the real inputs.
+fn generate(size: usize, seed: u64) -> String {
+    let mut random = Random::new(seed);
+    (0..size)
+        .map(|_| {
+            (0..size)
+                .map(|_| if random.below(2) == 0 { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn main() {
+    type Solver = fn(&Grid) -> i32;
+    let solvers: [(&str, Solver, Solver); 2] = [
+        ("part 1", day23::part1, day23::part1_hash_set),
+        ("part 2", day23::part2, day23::part2_hash_set),
+    ];
+    for seed in [0x2022_1223, 0xdead_beef] {
+        let input = day23::parse(&generate(72, seed));
+        println!("seed {seed:#x}, 72x72 tiles");
+        for (name, bits, hash_set) in solvers {
+            compare(
+                name,
+                3,
+                ("row bitmasks", || bits(&input)),
+                ("hash set", || hash_set(&input)),
+            );
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Compares the bitset frontier for day 24 with the hash set BFS, on generated
+//! inputs.
+//!
+//! Run with `cargo bench --bench day24`.
+
+mod common;
+
+use aoc_2022::{
+    days::day24::{self, Grid},
+    util::random::Random,
+};
+use common::compare;
+
+/// A basin with the same size as the real inputs, with blizzards everywhere
+/// except in the entrance and exit columns.
+fn generate(width: usize, height: usize, seed: u64) -> String {
+    let mut random = Random::new(seed);
+    let mut lines = vec![format!("#.{}", "#".repeat(width))];
+    for _ in 0..height {
+        let row = (1..=width)
+            .map(|x| {
+                let vertical = x != 1 && x != width;
+                match random.below(6) {
+                    1 => '>',
+                    2 => '<',
+                    3 if vertical => '^',
+                    4 if vertical => 'v',
+                    _ => '.',
+                }
+            })
+            .collect::<String>();
+        lines.push(format!("#{row}#"));
+    }
+    lines.push(format!("{}.#", "#".repeat(width)));
+    lines.join("\n")
+}
+
+fn main() {
+    type Solver = fn(&Grid) -> usize;
+    let solvers: [(&str, Solver, Solver); 2] = [
+        ("part 1", day24::part1, day24::part1_hash_set),
+        ("part 2", day24::part2, day24::part2_hash_set),
+    ];
+    for seed in [0x2022_1224, 0xdead_beef] {
+        let input = day24::parse(&generate(120, 25, seed));
+        println!("seed {seed:#x}, 120x25 cells");
+        for (name, bits, hash_set) in solvers {
+            compare(
+                name,
+                5,
+                ("bitset frontier", || bits(&input)),
+                ("hash set", || hash_set(&input)),
+            );
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use std::{cmp::Reverse, collections::BinaryHeap, usize};
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeSet, BinaryHeap, VecDeque},
+    fmt::{Display, Write},
+};
 
 use fxhash::FxHashMap;
 use regex::Regex;
@@ -38,8 +42,26 @@ struct MemoState {
     player_id: bool,
 }
 
+/// An optimal schedule, with the valves opened by each agent in order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Plan {
+    pub total_pressure: i32,
+    pub minutes: i32,
+    pub agents: Vec<Vec<Step>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Step {
+    pub valve: String,
+    pub flow_rate: i32,
+    // Minute (starting from 1) during which the valve is opened
+    pub opened_at: i32,
+    // Valves walked through to get here, ending with the opened valve
+    pub path: Vec<String>,
+}
+
 #[aoc_generator(day16)]
-fn parse(input: &str) -> Map {
+pub fn parse(input: &str) -> Map {
     let regex =
         Regex::new(r#"Valve (.+) has flow rate=(\d+); tunnels? leads? to valves? (.+)"#).unwrap();
     let name_map: FxHashMap<String, u16> = input
@@ -147,8 +169,222 @@ fn move_valve(
     res
 }
 
-#[aoc(day16, part1)]
-pub fn part1(input: &Map) -> i32 {
+/// Follows the choices that led to the memoized maximum, recording the valves
+/// that were opened. Mirrors `move_valve`, which must have already been run with
+/// the same memo.
+#[allow(clippy::too_many_arguments)]
+fn trace_valve(
+    input: &Map,
+    map: &Map,
+    node: &Node,
+    minutes: i32,
+    total: i32,
+    player_id: u8,
+    memo: &mut FxHashMap<MemoState, i32>,
+    visited: BitSet,
+    agents: &mut Vec<Vec<Step>>,
+) {
+    if minutes <= 1 {
+        return;
+    }
+    let handoff = (player_id != 0).then(|| {
+        let score = move_valve(map, &map.nodes[&map.aa_key], 26, 0, memo, visited);
+        (score, None)
+    });
+    let best = node
+        .parsed_neighbors
+        .iter()
+        .filter(|neigh| neigh.cost < minutes && !visited.contains(neigh.key as usize))
+        .map(|neighbor| {
+            let mut visited = visited;
+            visited.insert(neighbor.key as usize);
+            let score = move_valve(
+                map,
+                &map.nodes[&neighbor.key],
+                minutes - 1 - neighbor.cost,
+                player_id,
+                memo,
+                visited,
+            );
+            (score, Some(neighbor))
+        })
+        .chain(handoff)
+        .max_by_key(|(score, _)| *score);
+    match best {
+        Some((score, Some(neighbor))) if score > 0 => {
+            let new_node = &map.nodes[&neighbor.key];
+            let new_minutes = minutes - 1 - neighbor.cost;
+            agents.last_mut().unwrap().push(Step {
+                valve: new_node.name.clone(),
+                flow_rate: new_node.flow_rate,
+                opened_at: total - new_minutes,
+                path: tunnel_path(input, node.key, neighbor.key)
+                    .into_iter()
+                    .map(|key| input.nodes[&key].name.clone())
+                    .collect(),
+            });
+            let mut visited = visited;
+            visited.insert(neighbor.key as usize);
+            trace_valve(
+                input,
+                map,
+                new_node,
+                new_minutes,
+                total,
+                player_id,
+                memo,
+                visited,
+                agents,
+            );
+        }
+        Some((_, None)) => {
+            agents.push(vec![]);
+            trace_valve(
+                input,
+                map,
+                &map.nodes[&map.aa_key],
+                26,
+                26,
+                0,
+                memo,
+                visited,
+                agents,
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Shortest walk through the tunnels, excluding `from` and including `to`.
+fn tunnel_path(input: &Map, from: u16, to: u16) -> Vec<u16> {
+    let mut previous = FxHashMap::default();
+    let mut queue = VecDeque::from([from]);
+    previous.insert(from, from);
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            break;
+        }
+        for &next in &input.nodes[&node].linked_nodes {
+            if let Entry::Vacant(e) = previous.entry(next) {
+                e.insert(node);
+                queue.push_back(next);
+            }
+        }
+    }
+    let mut path = vec![to];
+    while let Some(&prev) = previous.get(path.last().unwrap()).filter(|&&p| p != from) {
+        path.push(prev);
+    }
+    path.reverse();
+    path
+}
+
+fn plan(input: &Map, minutes: i32, player_id: u8) -> Plan {
+    let map = compress(input);
+    let aa = &map.nodes[&map.aa_key];
+    let mut memo = FxHashMap::default();
+    let total_pressure = move_valve(&map, aa, minutes, player_id, &mut memo, BitSet::default());
+    let mut agents = vec![vec![]];
+    trace_valve(
+        input,
+        &map,
+        aa,
+        minutes,
+        minutes,
+        player_id,
+        &mut memo,
+        BitSet::default(),
+        &mut agents,
+    );
+    Plan {
+        total_pressure,
+        minutes,
+        agents,
+    }
+}
+
+/// The schedule behind [`part1`].
+pub fn part1_plan(input: &Map) -> Plan {
+    plan(input, 30, 0)
+}
+
+/// The schedule behind [`part2`], the second agent being the elephant.
+pub fn part2_plan(input: &Map) -> Plan {
+    plan(input, 26, 1)
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const AGENTS: [(&str, &str, &str); 2] =
+            [("You", "move", "open"), ("The elephant", "moves", "opens")];
+
+        // Minute -> (agent, valve, is opening)
+        let mut actions: FxHashMap<i32, Vec<(usize, &str, bool)>> = FxHashMap::default();
+        for (agent, steps) in self.agents.iter().enumerate() {
+            let mut minute = 1;
+            for step in steps {
+                for valve in &step.path {
+                    actions
+                        .entry(minute)
+                        .or_default()
+                        .push((agent, valve, false));
+                    minute += 1;
+                }
+                actions
+                    .entry(step.opened_at)
+                    .or_default()
+                    .push((agent, &step.valve, true));
+                minute = step.opened_at + 1;
+            }
+        }
+
+        for minute in 1..=self.minutes {
+            if minute > 1 {
+                writeln!(f)?;
+            }
+            writeln!(f, "== Minute {minute} ==")?;
+            let mut open = self
+                .agents
+                .iter()
+                .flatten()
+                .filter(|step| step.opened_at < minute)
+                .collect::<Vec<_>>();
+            open.sort_by(|a, b| a.valve.cmp(&b.valve));
+            let pressure: i32 = open.iter().map(|step| step.flow_rate).sum();
+            match open.as_slice() {
+                [] => writeln!(f, "No valves are open.")?,
+                [one] => writeln!(
+                    f,
+                    "Valve {} is open, releasing {pressure} pressure.",
+                    one.valve
+                )?,
+                [rest @ .., last] => {
+                    let names = rest.iter().map(|s| s.valve.as_str()).collect::<Vec<_>>();
+                    let comma = if rest.len() > 1 { "," } else { "" };
+                    writeln!(
+                        f,
+                        "Valves {}{comma} and {} are open, releasing {pressure} pressure.",
+                        names.join(", "),
+                        last.valve
+                    )?
+                }
+            }
+            for &(agent, valve, opening) in actions.get(&minute).into_iter().flatten() {
+                let (name, move_verb, open_verb) = AGENTS[agent];
+                if opening {
+                    writeln!(f, "{name} {open_verb} valve {valve}.")?;
+                } else {
+                    writeln!(f, "{name} {move_verb} to valve {valve}.")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps only the start valve and the valves with a non-zero flow rate, linking
+/// each of them to every other useful valve with the length of the shortest path.
+fn compress(input: &Map) -> Map {
     let mut known_paths = FxHashMap::default();
     let aa_key = input.aa_key;
     let mut nodes = input
@@ -173,13 +409,77 @@ pub fn part1(input: &Map) -> i32 {
             })
             .collect();
     }
-    let nodes: FxHashMap<_, _> = nodes.into_iter().map(|n| (n.key, n)).collect();
-    let aa = &nodes[&aa_key].clone();
     let mut map = input.clone();
-    map.nodes = nodes;
+    map.nodes = nodes.into_iter().map(|n| (n.key, n)).collect();
+    map
+}
+
+impl Map {
+    /// Graphviz DOT rendering of the tunnels, as parsed.
+    pub fn to_dot(&self) -> String {
+        self.write_dot(|node| node.linked_nodes.iter().map(|&key| (key, None)).collect())
+    }
+
+    /// Graphviz DOT rendering of the graph the solver actually explores: only
+    /// useful valves, with edges weighted by the minutes it takes to walk them.
+    pub fn compressed_dot(&self) -> String {
+        compress(self).write_dot(|node| {
+            node.parsed_neighbors
+                .iter()
+                .map(|neigh| (neigh.key, Some(neigh.cost)))
+                .collect()
+        })
+    }
+
+    fn write_dot(&self, edges: impl Fn(&Node) -> Vec<(u16, Option<i32>)>) -> String {
+        let mut nodes = self.nodes.values().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut out = String::from("graph valves {\n");
+        for node in &nodes {
+            let shape = if node.key == self.aa_key {
+                ", shape=doublecircle"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "  {} [label=\"{}\\n{}\"{shape}];",
+                node.name, node.name, node.flow_rate
+            )
+            .unwrap();
+        }
+        // Tunnels go both ways, so only keep one edge per pair
+        let edges = nodes
+            .iter()
+            .flat_map(|node| {
+                edges(node).into_iter().map(|(other, cost)| {
+                    let other = &self.nodes[&other].name;
+                    let (a, b) = if node.name < *other {
+                        (&node.name, other)
+                    } else {
+                        (other, &node.name)
+                    };
+                    (a, b, cost)
+                })
+            })
+            .collect::<BTreeSet<_>>();
+        for (a, b, cost) in edges {
+            match cost {
+                Some(cost) => writeln!(out, "  {a} -- {b} [label={cost}];").unwrap(),
+                None => writeln!(out, "  {a} -- {b};").unwrap(),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[aoc(day16, part1)]
+pub fn part1(input: &Map) -> i32 {
+    let map = compress(input);
     move_valve(
         &map,
-        aa,
+        &map.nodes[&map.aa_key],
         30,
         0,
         &mut FxHashMap::with_capacity_and_hasher(2_500_000, Default::default()),
@@ -189,37 +489,10 @@ pub fn part1(input: &Map) -> i32 {
 
 #[aoc(day16, part2)]
 pub fn part2(input: &Map) -> i32 {
-    let mut known_paths = FxHashMap::default();
-    let aa_key = input.aa_key;
-    let mut nodes = input
-        .nodes
-        .values()
-        .filter(|node| node.key == aa_key || node.flow_rate > 0)
-        .cloned()
-        .collect::<Vec<_>>();
-    for node in &nodes {
-        dijkstra(&input.nodes, node, &mut known_paths);
-    }
-    for parent in &mut nodes {
-        parent.parsed_neighbors = known_paths[&parent.key]
-            .iter()
-            .filter_map(|(dest, cost)| {
-                let node = &input.nodes[dest];
-                (*dest != parent.key && node.flow_rate > 0).then_some(Neighbor {
-                    key: *dest,
-                    gain: node.flow_rate,
-                    cost: *cost,
-                })
-            })
-            .collect();
-    }
-    let nodes: FxHashMap<_, _> = nodes.into_iter().map(|n| (n.key, n)).collect();
-    let mut map = input.clone();
-    let aa = &nodes[&aa_key].clone();
-    map.nodes = nodes;
+    let map = compress(input);
     move_valve(
         &map,
-        aa,
+        &map.nodes[&map.aa_key],
         26,
         1,
         &mut FxHashMap::with_capacity_and_hasher(2_500_000, fxhash::FxBuildHasher::default()),
@@ -260,4 +533,80 @@ Valve II has flow rate=0; tunnels lead to valves AA, JJ
 Valve JJ has flow rate=21; tunnel leads to valve II"#;
         assert_eq!(part2(&parse(input)), 1707);
     }
+
+    #[test]
+    fn part1_plan_example() {
+        let input = r#"Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
+Valve BB has flow rate=13; tunnels lead to valves CC, AA
+Valve CC has flow rate=2; tunnels lead to valves DD, BB
+Valve DD has flow rate=20; tunnels lead to valves CC, AA, EE
+Valve EE has flow rate=3; tunnels lead to valves FF, DD
+Valve FF has flow rate=0; tunnels lead to valves EE, GG
+Valve GG has flow rate=0; tunnels lead to valves FF, HH
+Valve HH has flow rate=22; tunnel leads to valve GG
+Valve II has flow rate=0; tunnels lead to valves AA, JJ
+Valve JJ has flow rate=21; tunnel leads to valve II"#;
+        let plan = part1_plan(&parse(input));
+        assert_eq!(plan.total_pressure, 1651);
+        let order = plan.agents[0]
+            .iter()
+            .map(|s| s.valve.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["DD", "BB", "JJ", "HH", "EE", "CC"]);
+        assert_eq!(plan.agents[0][2].path, ["AA", "II", "JJ"]);
+        let text = plan.to_string();
+        assert!(text.starts_with("== Minute 1 ==\nNo valves are open.\nYou move to valve DD.\n"));
+        assert!(text.contains(
+            "== Minute 26 ==\nValves BB, CC, DD, EE, HH, and JJ are open, releasing 81 pressure.\n"
+        ));
+    }
+
+    #[test]
+    fn part2_plan_example() {
+        let input = r#"Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
+Valve BB has flow rate=13; tunnels lead to valves CC, AA
+Valve CC has flow rate=2; tunnels lead to valves DD, BB
+Valve DD has flow rate=20; tunnels lead to valves CC, AA, EE
+Valve EE has flow rate=3; tunnels lead to valves FF, DD
+Valve FF has flow rate=0; tunnels lead to valves EE, GG
+Valve GG has flow rate=0; tunnels lead to valves FF, HH
+Valve HH has flow rate=22; tunnel leads to valve GG
+Valve II has flow rate=0; tunnels lead to valves AA, JJ
+Valve JJ has flow rate=21; tunnel leads to valve II"#;
+        let plan = part2_plan(&parse(input));
+        assert_eq!(plan.total_pressure, 1707);
+        let released: i32 = plan
+            .agents
+            .iter()
+            .flatten()
+            .map(|step| step.flow_rate * (plan.minutes - step.opened_at))
+            .sum();
+        assert_eq!(released, 1707);
+    }
+
+    #[test]
+    fn dot_example() {
+        let input = r#"Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
+Valve BB has flow rate=13; tunnels lead to valves CC, AA
+Valve CC has flow rate=2; tunnels lead to valves DD, BB
+Valve DD has flow rate=20; tunnels lead to valves CC, AA, EE
+Valve EE has flow rate=3; tunnels lead to valves FF, DD
+Valve FF has flow rate=0; tunnels lead to valves EE, GG
+Valve GG has flow rate=0; tunnels lead to valves FF, HH
+Valve HH has flow rate=22; tunnel leads to valve GG
+Valve II has flow rate=0; tunnels lead to valves AA, JJ
+Valve JJ has flow rate=21; tunnel leads to valve II"#;
+        let map = parse(input);
+
+        let raw = map.to_dot();
+        assert!(raw.starts_with("graph valves {\n  AA [label=\"AA\\n0\", shape=doublecircle];\n"));
+        assert_eq!(raw.matches(" -- ").count(), 10);
+        assert!(raw.contains("  GG -- HH;\n"));
+
+        let compressed = map.compressed_dot();
+        assert_eq!(compressed.matches("[label=\"").count(), 7);
+        assert_eq!(compressed.matches(" -- ").count(), 21);
+        assert!(compressed.contains("  AA -- JJ [label=2];\n"));
+        assert!(compressed.contains("  BB -- JJ [label=3];\n"));
+    }
 }
@@ -1,236 +1,643 @@
-use fxhash::FxHashMap;
+use std::{collections::VecDeque, fmt::Display, fs, io, ops::Range, path::Path, str::FromStr};
 
-struct Grid {
-    inner: FxHashMap<(isize, isize), usize>,
-    wind_direction: bool, // right: true
+use crate::util::{
+    cycle::Detector,
+    image::{Color, Image},
+};
+
+// Rows are stored as bitmasks, bit x being set if column x is occupied.
+type Row = u32;
+
+// Rows that no rock can reach any more are dropped from the bottom of the
+// chamber, but at least this many rows below the top are kept for rendering.
+const KEPT_ROWS: usize = 128;
+// Cycles are only looked for while rocks can't get any deeper than this below
+// the top. Deeper states come from open shafts, and are costly to compare.
+const MAX_STATE_ROWS: usize = 256;
+const MAX_ROCK_HEIGHT: usize = 8;
+
+const PUZZLE_RULES: &str = "width=7
+left=2
+gap=3
+
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##";
+
+/// Chamber layout and rock set for a simulation.
+///
+/// The text description starts with an optional block of `key=value` settings
+/// (`width`, `left` for the empty columns to the left of a new rock, `gap` for the
+/// empty rows below it), followed by the rocks in the puzzle's format, separated
+/// by blank lines. Rows of `.` above or below a rock's cells are left out, so
+/// that rocks rest on their cells.
+pub struct Rules {
+    width: usize,
+    spawn_gap: usize,
+    shapes: Vec<Shape>,
+}
+
+/// Problems with a description of [`Rules`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RulesError {
+    // Settings must be `key=number`
+    BadSetting(String),
+    UnknownSetting(String),
+    // Chambers are 1 to 32 columns wide
+    BadWidth(usize),
+    RockTooTall(usize),
+    // Rocks must fit between the left gap and the right wall
+    RockTooWide(usize),
+    BadChar(char),
+    // Rocks need at least one `#`
+    EmptyRock,
+    NoRocks,
 }
 
-struct Piece {
-    area: &'static [(isize, isize)],
-    piece_id: usize,
+struct Chamber {
+    // Rows from `base` up to `height`
+    rows: VecDeque<Row>,
+    base: usize,
+    height: usize,
+    // Mask of the columns inside the chamber
+    full: Row,
+    // Length at which to look for rows to drop
+    trim_at: usize,
+}
+
+/// A rock in its spawn position, from the bottom row to the top.
+#[derive(Clone, Copy)]
+struct Shape {
+    rows: [Row; MAX_ROCK_HEIGHT],
+    height: usize,
 }
 
 #[derive(PartialEq, Eq, Hash)]
 struct MemoState {
-    line: Vec<(isize, isize)>,
-    dot: Vec<(isize, isize)>,
+    shape_index: usize,
     wind_index: usize,
+    // Every cell that later rocks can get to, which is all that decides
+    // where they come to rest
+    reachable: Vec<Row>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        PUZZLE_RULES.parse().unwrap()
+    }
 }
 
-static PIECE_AREA_LINE: [(isize, isize); 4] = [(0, 0), (1, 0), (2, 0), (3, 0)];
-static PIECE_AREA_CROSS: [(isize, isize); 5] = [(1, 0), (0, 1), (1, 1), (2, 1), (1, 2)];
-static PIECE_AREA_SEAT: [(isize, isize); 5] = [(2, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
-static PIECE_AREA_COLUMN: [(isize, isize); 4] = [(0, 0), (0, 1), (0, 2), (0, 3)];
-static PIECE_AREA_DOT: [(isize, isize); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+impl FromStr for Rules {
+    type Err = RulesError;
 
-impl Grid {
-    fn new(first_direction: bool) -> Self {
-        Self {
-            inner: FxHashMap::default(),
-            wind_direction: first_direction,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut width, mut spawn_left, mut spawn_gap) = (7, 2, 3);
+        let mut blocks = s.trim().split("\n\n").peekable();
+        if let Some(settings) = blocks.next_if(|block| block.contains('=')) {
+            for line in settings.lines() {
+                let bad_setting = || RulesError::BadSetting(line.to_string());
+                let (key, value) = line.split_once('=').ok_or_else(bad_setting)?;
+                let value = value.trim().parse().map_err(|_| bad_setting())?;
+                match key.trim() {
+                    "width" => width = value,
+                    "left" => spawn_left = value,
+                    "gap" => spawn_gap = value,
+                    k => return Err(RulesError::UnknownSetting(k.to_string())),
+                }
+            }
+        }
+        if width == 0 || width > Row::BITS as usize {
+            return Err(RulesError::BadWidth(width));
         }
+        let shapes = blocks
+            .map(|block| {
+                let lines = block.lines().map(str::trim).collect::<Vec<_>>();
+                if lines.len() > MAX_ROCK_HEIGHT {
+                    return Err(RulesError::RockTooTall(lines.len()));
+                }
+                let mut rows = [0; MAX_ROCK_HEIGHT];
+                for (row, line) in rows.iter_mut().zip(lines.iter().rev()) {
+                    if spawn_left + line.len() > width {
+                        return Err(RulesError::RockTooWide(line.len()));
+                    }
+                    for (x, c) in line.chars().enumerate() {
+                        match c {
+                            '#' => *row |= 1 << (spawn_left + x),
+                            '.' => {}
+                            c => return Err(RulesError::BadChar(c)),
+                        }
+                    }
+                }
+                let rows_used = &rows[..lines.len()];
+                let bottom = rows_used
+                    .iter()
+                    .position(|&row| row != 0)
+                    .ok_or(RulesError::EmptyRock)?;
+                let top = rows_used.iter().rposition(|&row| row != 0).unwrap() + 1;
+                rows.copy_within(bottom..top, 0);
+                rows[top - bottom..].fill(0);
+                Ok(Shape {
+                    rows,
+                    height: top - bottom,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if shapes.is_empty() {
+            return Err(RulesError::NoRocks);
+        }
+        Ok(Self {
+            width,
+            spawn_gap,
+            shapes,
+        })
     }
+}
 
-    fn is_obstructed(&self, pos: (isize, isize), not_piece: &Piece) -> bool {
-        pos.0 < 0
-            || pos.0 > 6
-            || pos.1 < 0
-            || self
-                .inner
-                .get(&pos)
-                .map(|&p| p != not_piece.piece_id)
-                .unwrap_or(false)
+impl Display for RulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadSetting(line) => write!(f, "invalid setting {line:?}"),
+            Self::UnknownSetting(key) => write!(f, "unknown setting {key}"),
+            Self::BadWidth(width) => write!(f, "chamber width {width} isn't between 1 and 32"),
+            Self::RockTooTall(height) => {
+                write!(
+                    f,
+                    "rock of height {height} is taller than {MAX_ROCK_HEIGHT}"
+                )
+            }
+            Self::RockTooWide(width) => write!(f, "rock of width {width} doesn't fit"),
+            Self::BadChar(c) => write!(f, "invalid char {c:?}"),
+            Self::EmptyRock => write!(f, "rock without any '#'"),
+            Self::NoRocks => write!(f, "no rocks"),
+        }
     }
 }
 
-impl Piece {
-    fn new(shape: &'static [(isize, isize)], id: usize) -> Self {
+impl std::error::Error for RulesError {}
+
+impl Chamber {
+    fn new(width: usize) -> Self {
         Self {
-            area: shape,
-            piece_id: id,
-        }
-    }
-
-    fn move_all(
-        &self,
-        grid: &mut Grid,
-        top: (isize, isize),
-        direction: (isize, isize),
-    ) -> (bool, (isize, isize)) {
-        let mut moves = [None; 16];
-        for &(dx, dy) in self.area {
-            let (old_x, old_y) = (top.0 + dx, top.1 - dy);
-            let (new_x, new_y) = (old_x + direction.0, old_y + direction.1);
-            if grid.is_obstructed((new_x, new_y), self) {
-                return (true, top);
-            }
-            moves[dx as usize + dy as usize * 4] = Some(((old_x, old_y), (new_x, new_y)));
+            rows: VecDeque::new(),
+            base: 0,
+            height: 0,
+            full: Row::MAX >> (Row::BITS as usize - width),
+            trim_at: 2 * KEPT_ROWS,
         }
-        for (old, _) in moves.iter().flatten() {
-            grid.inner.remove(old);
+    }
+
+    /// Row `y`, dropped rows counting as full since no rock can get there.
+    #[inline]
+    fn row(&self, y: usize) -> Row {
+        if y >= self.height {
+            0
+        } else if y < self.base {
+            Row::MAX
+        } else {
+            self.rows[y - self.base]
         }
-        for (_, new) in moves.iter().flatten().copied() {
-            grid.inner.insert(new, self.piece_id);
+    }
+
+    fn collides(&self, shape: &Shape, y: usize) -> bool {
+        (0..shape.height).any(|i| self.row(y + i) & shape.rows[i] != 0)
+    }
+
+    fn place(&mut self, shape: &Shape, y: usize) {
+        self.height = self.height.max(y + shape.height);
+        self.rows.resize(self.height - self.base, 0);
+        for i in 0..shape.height {
+            self.rows[y + i - self.base] |= shape.rows[i];
+        }
+        if self.rows.len() >= self.trim_at {
+            self.trim();
         }
-        (false, (top.0 + direction.0, top.1 + direction.1))
     }
 
-    fn do_move(&self, grid: &mut Grid, top_pos: (isize, isize)) -> (bool, (isize, isize)) {
-        let (_, new_pos) =
-            self.move_all(grid, top_pos, (if grid.wind_direction { 1 } else { -1 }, 0));
-        self.move_all(grid, new_pos, (0, -1))
+    /// Cells that a rock could still get to from above, for every row from
+    /// the top down to the deepest one it can reach. Rocks only move sideways
+    /// and down, so a cell is reachable if the cell above it or next to it is.
+    fn reachable(&self) -> impl Iterator<Item = Row> + '_ {
+        let mut reach = self.full;
+        (self.base..self.height).rev().map_while(move |y| {
+            let empty = !self.row(y) & self.full;
+            reach &= empty;
+            loop {
+                let spread = (reach | reach << 1 | reach >> 1) & empty;
+                if spread == reach {
+                    break;
+                }
+                reach = spread;
+            }
+            (reach != 0).then_some(reach)
+        })
+    }
+
+    /// Drops the rows below both the deepest reachable row and the
+    /// [`KEPT_ROWS`] topmost ones.
+    fn trim(&mut self) {
+        let deepest = self.height - self.reachable().count();
+        let keep_from = deepest.min(self.height.saturating_sub(KEPT_ROWS));
+        self.rows.drain(..keep_from - self.base);
+        self.base = keep_from;
+        // Open shafts keep every row, so don't look again until the chamber
+        // has doubled in size
+        self.trim_at = (2 * self.rows.len()).max(2 * KEPT_ROWS);
+    }
+
+    /// Pushes a falling rock with a jet, then moves it down one unit. Returns
+    /// `true` if the rock can't fall any further, in which case it should be placed.
+    fn advance(&self, rules: &Rules, shape: &mut Shape, y: &mut usize, right: bool) -> bool {
+        if let Some(pushed) = shape.push(right, rules.width) {
+            if !self.collides(&pushed, *y) {
+                *shape = pushed;
+            }
+        }
+        if *y == 0 || self.collides(shape, *y - 1) {
+            return true;
+        }
+        *y -= 1;
+        false
+    }
+
+    /// Drops a rock from the top, returning the new wind index.
+    fn drop_rock(
+        &mut self,
+        rules: &Rules,
+        shape: &Shape,
+        wind: &[bool],
+        mut wind_index: usize,
+    ) -> usize {
+        let mut shape = *shape;
+        let mut y = self.height + rules.spawn_gap;
+        loop {
+            let rested = self.advance(rules, &mut shape, &mut y, wind[wind_index]);
+            wind_index = (wind_index + 1) % wind.len();
+            if rested {
+                self.place(&shape, y);
+                return wind_index;
+            }
+        }
     }
 }
 
-#[inline]
-fn set_to_sorted_vec(input: &FxHashMap<(isize, isize), usize>) -> Vec<(isize, isize)> {
-    let mut copy = input.keys().copied().collect::<Vec<_>>();
-    let min_y = copy
-        .iter()
-        .map(|(_, y)| y)
-        .min()
-        .copied()
-        .unwrap_or_default();
-    for tup in &mut copy {
-        tup.1 -= min_y;
+impl Shape {
+    /// Moves the rock sideways, or returns `None` if it would hit a wall.
+    fn push(&self, right: bool, width: usize) -> Option<Self> {
+        let mut rows = self.rows;
+        let wall = if right { 1 << (width - 1) } else { 1 };
+        if rows.iter().any(|row| row & wall != 0) {
+            return None;
+        }
+        for row in &mut rows {
+            *row = if right { *row << 1 } else { *row >> 1 };
+        }
+        Some(Self { rows, ..*self })
     }
-    copy.sort();
-    copy
 }
 
-#[aoc_generator(day17)]
-fn parse(input: &str) -> Vec<bool> {
-    input.chars().map(|c| c == '>').collect()
+/// Step-by-step simulation, for inspecting the tower.
+pub struct Simulation<'a> {
+    rules: &'a Rules,
+    jets: &'a [bool],
+    chamber: Chamber,
+    wind_index: usize,
+    rested: usize,
+    falling: Option<(Shape, usize)>,
 }
 
-#[aoc(day17, part1)]
-pub fn part1(input: &[bool]) -> isize {
-    let mut grid = Grid::new(input[0]);
-    let mut wind_index = 0;
-    let order = [
-        // shape, height
-        (PIECE_AREA_LINE.as_ref(), 1),
-        (&PIECE_AREA_CROSS, 3),
-        (&PIECE_AREA_SEAT, 3),
-        (&PIECE_AREA_COLUMN, 4),
-        (&PIECE_AREA_DOT, 2),
-    ];
-    let mut rested = 0;
-    while rested < 2022 {
-        let (shape, height) = order[rested % order.len()];
-        let new_piece = Piece::new(shape, rested);
-        let max_y = grid
-            .inner
-            .keys()
-            .max_by_key(|pos| pos.1)
-            .copied()
-            .unwrap_or((0, -1))
-            .1;
-        let mut pos = (2, max_y + 3 + height);
-        while let (false, new_pos) = new_piece.do_move(&mut grid, pos) {
-            pos = new_pos;
-            wind_index = (wind_index + 1) % input.len();
-            grid.wind_direction = input[wind_index];
-        }
-        wind_index = (wind_index + 1) % input.len();
-        grid.wind_direction = input[wind_index];
-        rested += 1;
-    }
-    grid.inner.into_keys().max_by_key(|pos| pos.1).unwrap().1 + 1
+impl<'a> Simulation<'a> {
+    pub fn new(jets: &'a [bool], rules: &'a Rules) -> Self {
+        Self {
+            rules,
+            jets,
+            chamber: Chamber::new(rules.width),
+            wind_index: 0,
+            rested: 0,
+            falling: None,
+        }
+    }
+
+    pub fn rested(&self) -> usize {
+        self.rested
+    }
+
+    pub fn height(&self) -> usize {
+        self.chamber.height
+    }
+
+    /// Spawns a new rock if none is falling, otherwise pushes the falling rock
+    /// and moves it down.
+    pub fn step(&mut self) {
+        let Some((mut shape, mut y)) = self.falling else {
+            let shape = self.rules.shapes[self.rested % self.rules.shapes.len()];
+            self.falling = Some((shape, self.chamber.height + self.rules.spawn_gap));
+            return;
+        };
+        let right = self.jets[self.wind_index];
+        self.wind_index = (self.wind_index + 1) % self.jets.len();
+        if self.chamber.advance(self.rules, &mut shape, &mut y, right) {
+            self.chamber.place(&shape, y);
+            self.rested += 1;
+            self.falling = None;
+        } else {
+            self.falling = Some((shape, y));
+        }
+    }
+
+    /// Runs until the next rock comes to rest.
+    pub fn drop_rock(&mut self) {
+        let rested = self.rested;
+        while self.rested == rested {
+            self.step();
+        }
+    }
+
+    /// Text rendering in the puzzle's format, with the falling rock drawn
+    /// as `@`. Only the rows that are still kept in memory are drawn.
+    pub fn render(&self) -> String {
+        let falling = self.falling.as_ref();
+        let top = falling.map_or(0, |(shape, y)| y + shape.height);
+        let top = top.max(self.chamber.height);
+        let bottom = self.chamber.base;
+        let mut out = String::new();
+        for y in (bottom..top).rev() {
+            let row = self.chamber.row(y);
+            let falling_row = falling
+                .filter(|(shape, fy)| (*fy..fy + shape.height).contains(&y))
+                .map_or(0, |(shape, fy)| shape.rows[y - fy]);
+            out.push('|');
+            for x in 0..self.rules.width {
+                out.push(if falling_row & 1 << x != 0 {
+                    '@'
+                } else if row & 1 << x != 0 {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            out.push_str("|\n");
+        }
+        if bottom == 0 {
+            out.push('+');
+            out.push_str(&"-".repeat(self.rules.width));
+            out.push_str("+\n");
+        }
+        out
+    }
+
+    /// Same as [`Simulation::render`], with every cell drawn as a
+    /// `scale * scale` square.
+    pub fn to_image(&self, scale: usize) -> Image {
+        const EMPTY: Color = [20, 20, 40];
+        const ROCK: Color = [160, 160, 160];
+        const FALLING: Color = [230, 140, 30];
+        const WALL: Color = [100, 70, 50];
+
+        let text = self.render();
+        let lines = text.lines().collect::<Vec<_>>();
+        let mut image = Image::new((self.rules.width + 2) * scale, lines.len() * scale);
+        for (y, line) in lines.into_iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let color = match c {
+                    '.' => EMPTY,
+                    '#' => ROCK,
+                    '@' => FALLING,
+                    _ => WALL,
+                };
+                image.fill_cell(x, y, scale, color);
+            }
+        }
+        image
+    }
 }
 
-#[aoc(day17, part2)]
-pub fn part2(input: &[bool]) -> isize {
-    const TARGET: usize = 1000000000000;
+/// Renders the tower as each rock in `rocks` starts falling.
+pub fn frames(jets: &[bool], rules: &Rules, rocks: Range<usize>, scale: usize) -> Vec<Image> {
+    let mut sim = Simulation::new(jets, rules);
+    while sim.rested() < rocks.start {
+        sim.drop_rock();
+    }
+    rocks
+        .map(|_| {
+            sim.step();
+            let image = sim.to_image(scale);
+            sim.drop_rock();
+            image
+        })
+        .collect()
+}
+
+/// Writes the [`frames`] to `dir`, as `rock_<n>.png` or `rock_<n>.ppm`.
+pub fn export_frames(
+    dir: &Path,
+    png: bool,
+    jets: &[bool],
+    rules: &Rules,
+    rocks: Range<usize>,
+    scale: usize,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (rock, image) in rocks.clone().zip(frames(jets, rules, rocks, scale)) {
+        if png {
+            fs::write(dir.join(format!("rock_{rock:05}.png")), image.to_png())?;
+        } else {
+            fs::write(dir.join(format!("rock_{rock:05}.ppm")), image.to_ppm())?;
+        }
+    }
+    Ok(())
+}
 
-    let mut grid = Grid::new(input[0]);
-    let mut visited: FxHashMap<MemoState, (usize, isize)> = FxHashMap::default();
-    let mut max_ys: FxHashMap<usize, isize> = FxHashMap::default();
+/// Height of the tower after `rocks` rocks have come to rest, extrapolated as
+/// soon as the simulation repeats itself.
+pub fn tower_height(jets: &[bool], rules: &Rules, rocks: usize) -> usize {
+    let mut chamber = Chamber::new(rules.width);
+    let mut detector = Detector::new();
+    // Rock at which the detector started recording
+    let mut first = 0;
     let mut wind_index = 0;
-    let order = [
-        // shape, height
-        (PIECE_AREA_LINE.as_ref(), 1),
-        (&PIECE_AREA_CROSS, 3),
-        (&PIECE_AREA_SEAT, 3),
-        (&PIECE_AREA_COLUMN, 4),
-        (&PIECE_AREA_DOT, 2),
-    ];
-    let mut rested = 0;
-    let mut last_line = vec![];
-    while rested < TARGET {
-        let piece_index = rested % order.len();
-        if piece_index == 0 {
-            last_line = set_to_sorted_vec(&grid.inner);
-        } else if piece_index == 4 {
+    for rested in 0..rocks {
+        let shape_index = rested % rules.shapes.len();
+        let reachable = chamber
+            .reachable()
+            .take(MAX_STATE_ROWS + 1)
+            .collect::<Vec<_>>();
+        if reachable.len() > MAX_STATE_ROWS {
+            // Start over once the shaft is filled in
+            detector = Detector::new();
+            first = rested + 1;
+        } else {
             let memo_state = MemoState {
-                dot: set_to_sorted_vec(&grid.inner),
-                line: last_line.clone(),
+                shape_index,
                 wind_index,
+                reachable,
             };
-            if let Some((rested_src, max_y_src)) = visited.get(&memo_state) {
-                let diff = (rested - rested_src) as isize;
-                let y_diff = grid.inner.keys().max_by_key(|pos| pos.1).unwrap().1 + 1 - max_y_src;
-                let (factor, real_target) = (TARGET as isize / diff, TARGET as isize % diff);
-                let real_target_max_y = max_ys[&(real_target as usize)];
-                return real_target_max_y + y_diff * factor;
+            if let Some(cycle) = detector.record(memo_state, chamber.height as i64) {
+                return detector.extrapolate(cycle, rocks - first) as usize;
             }
+        }
 
-            visited.insert(
-                memo_state,
-                (
-                    rested,
-                    grid.inner
-                        .keys()
-                        .max_by_key(|pos| pos.1)
-                        .unwrap_or(&(0, 0))
-                        .1
-                        + 1,
-                ),
-            );
-        }
-
-        let (shape, height) = order[piece_index];
-        let new_piece = Piece::new(shape, rested);
-        let max_y = grid
-            .inner
-            .keys()
-            .max_by_key(|pos| pos.1)
-            .copied()
-            .unwrap_or((0, -1))
-            .1;
-        let mut pos = (2, max_y + 3 + height);
-        while let (false, new_pos) = new_piece.do_move(&mut grid, pos) {
-            pos = new_pos;
-            wind_index = (wind_index + 1) % input.len();
-            grid.wind_direction = input[wind_index];
-        }
-        wind_index = (wind_index + 1) % input.len();
-        grid.wind_direction = input[wind_index];
-        rested += 1;
-
-        // If one row is fully covered, discard everything below it
-        let mut counts: FxHashMap<isize, isize> = FxHashMap::default();
-        for (_, y) in grid.inner.keys() {
-            *counts.entry(*y).or_default() += 1isize;
-        }
-        for (y, _) in counts.iter().filter(|(_, v)| **v == 7) {
-            grid.inner.retain(|(_, y1), _| y1 >= y);
-        }
-        max_ys.insert(
-            rested,
-            grid.inner.keys().max_by_key(|pos| pos.1).unwrap().1 + 1,
-        );
+        let shape = &rules.shapes[shape_index];
+        wind_index = chamber.drop_rock(rules, shape, jets, wind_index);
     }
-    max_ys[&rested]
+    chamber.height
+}
+
+#[aoc_generator(day17)]
+pub fn parse(input: &str) -> Vec<bool> {
+    input.chars().map(|c| c == '>').collect()
+}
+
+#[aoc(day17, part1)]
+pub fn part1(input: &[bool]) -> usize {
+    tower_height(input, &Rules::default(), 2022)
+}
+
+#[aoc(day17, part2)]
+pub fn part2(input: &[bool]) -> usize {
+    tower_height(input, &Rules::default(), 1000000000000)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::random::Random;
 
     #[test]
     fn part1_example() {
         let input = r#">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"#;
         assert_eq!(part1(&parse(input)), 3068);
     }
+
+    #[test]
+    fn part2_example() {
+        let input = r#">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"#;
+        assert_eq!(part2(&parse(input)), 1514285714288);
+    }
+
+    #[test]
+    fn custom_rules() {
+        let input = parse(r#">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"#);
+        // Rocks that fill the whole width stack without gaps
+        let rules = "width=4\nleft=0\n\n####\n\n####\n####".parse().unwrap();
+        assert_eq!(tower_height(&input, &rules, 10), 15);
+        // The puzzle rules, written out
+        let rules: Rules = PUZZLE_RULES.replace("gap=3\n", "").parse().unwrap();
+        assert_eq!(tower_height(&input, &rules, 2022), 3068);
+        // A shaft in the middle, that the last rock falls all the way down
+        let depth = 10 * KEPT_ROWS;
+        let rules = format!("width=3\nleft=0\ngap=0\n\n{}#", "#.#\n\n".repeat(depth));
+        let rules: Rules = rules.parse().unwrap();
+        assert_eq!(tower_height(&[true], &rules, depth + 1), depth);
+        let wide: Rules = PUZZLE_RULES.replace("width=7", "width=9").parse().unwrap();
+        assert_ne!(tower_height(&input, &wide, 2022), 3068);
+
+        let error = |s: &str| s.parse::<Rules>().err();
+        assert_eq!(
+            error("width=x\n\n#"),
+            Some(RulesError::BadSetting("width=x".into()))
+        );
+        assert_eq!(
+            error("height=3\n\n#"),
+            Some(RulesError::UnknownSetting("height".into()))
+        );
+        assert_eq!(error("width=40\n\n#"), Some(RulesError::BadWidth(40)));
+        assert_eq!(error("width=3\n\n####"), Some(RulesError::RockTooWide(4)));
+        assert_eq!(
+            error("#\n#\n#\n#\n#\n#\n#\n#\n#"),
+            Some(RulesError::RockTooTall(9))
+        );
+        assert_eq!(error("#x#"), Some(RulesError::BadChar('x')));
+        assert_eq!(error("width=7"), Some(RulesError::NoRocks));
+        assert_eq!(error("#\n\n..\n.."), Some(RulesError::EmptyRock));
+        // Blank rows around a rock don't hold it up
+        let padded: Rules = "..\n#.\n..\n\n.#\n..".parse().unwrap();
+        let plain: Rules = "#\n\n.#".parse().unwrap();
+        assert_eq!(
+            tower_height(&input, &padded, 100),
+            tower_height(&input, &plain, 100)
+        );
+    }
+
+    #[test]
+    fn cycles_match_simulation() {
+        let mut random = Random::new(17);
+        for _ in 0..100 {
+            let width = random.range(2, 8) as usize;
+            let left = random.below(width as u64) as usize;
+            let mut rules = format!("width={width}\nleft={left}\ngap={}\n", random.below(4));
+            for _ in 0..random.range(1, 4) {
+                let rock_width = random.range(1, (width - left) as i64) as usize;
+                let mut rock = String::new();
+                for _ in 0..random.range(1, 3) {
+                    rock.push('\n');
+                    rock.extend((0..rock_width).map(|_| ['.', '#'][random.below(2) as usize]));
+                }
+                if !rock.contains('#') {
+                    rock.replace_range(1..2, "#");
+                }
+                rules += &rock;
+                rules.push('\n');
+            }
+            let rules: Rules = rules.parse().unwrap();
+            let jets = (0..random.range(1, 40))
+                .map(|_| random.below(2) == 1)
+                .collect::<Vec<_>>();
+            let mut sim = Simulation::new(&jets, &rules);
+            for _ in 0..3000 {
+                sim.drop_rock();
+            }
+            assert_eq!(tower_height(&jets, &rules, 3000), sim.height());
+        }
+    }
+
+    #[test]
+    fn render_example() {
+        let input = parse(r#">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"#);
+        let rules = Rules::default();
+        let mut sim = Simulation::new(&input, &rules);
+        sim.step();
+        assert_eq!(
+            sim.render(),
+            "|..@@@@.|\n|.......|\n|.......|\n|.......|\n+-------+\n"
+        );
+        sim.drop_rock();
+        sim.step();
+        assert_eq!(
+            sim.render(),
+            r#"|...@...|
+|..@@@..|
+|...@...|
+|.......|
+|.......|
+|.......|
+|..####.|
++-------+
+"#
+        );
+        for _ in 1..2022 {
+            sim.drop_rock();
+        }
+        assert_eq!(sim.height(), 3068);
+
+        let frames = frames(&input, &rules, 0..3, 2);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].width(), 18);
+        assert_eq!(frames[1].height(), 16);
+        // Falling rock in the top row
+        assert_eq!(frames[1].get(8, 0), [230, 140, 30]);
+    }
 }
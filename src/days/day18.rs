@@ -1,6 +1,6 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, fmt::Write};
 
-use fxhash::FxHashSet;
+use fxhash::{FxHashMap, FxHashSet};
 
 #[derive(Debug)]
 pub struct Cube {
@@ -9,25 +9,361 @@ pub struct Cube {
     z: isize,
 }
 
-struct Space<'c> {
-    cubes: &'c FxHashSet<(isize, isize, isize)>,
-    min: isize,
-    max: isize,
+pub type Coord = (isize, isize, isize);
+
+/// Storage for a set of cubes within known bounds.
+pub trait Voxels {
+    fn new(min: Coord, max: Coord) -> Self;
+    fn contains(&self, cube: Coord) -> bool;
+    /// Returns `false` if the cube was already in the set.
+    fn insert(&mut self, cube: Coord) -> bool;
+}
+
+/// Dense bit grid over a box, with one bit per cube.
+pub struct BitGrid {
+    min: Coord,
+    size: (usize, usize, usize),
+    bits: Vec<u64>,
+}
+
+struct Space {
+    min: Coord,
+    max: Coord,
 }
 
-impl<'a> Space<'a> {
+/// Lava droplet, along with the air around it.
+pub struct Droplet<V = BitGrid> {
+    cubes: Vec<Coord>,
+    lava: V,
+    // Air that can be reached from outside the droplet
+    exterior: V,
+    pockets: Vec<Vec<Coord>>,
+    space: Space,
+}
+
+impl Voxels for FxHashSet<Coord> {
+    fn new(_min: Coord, _max: Coord) -> Self {
+        Self::default()
+    }
+
+    fn contains(&self, cube: Coord) -> bool {
+        self.contains(&cube)
+    }
+
+    fn insert(&mut self, cube: Coord) -> bool {
+        self.insert(cube)
+    }
+}
+
+impl BitGrid {
+    #[inline]
+    fn index(&self, cube: Coord) -> Option<usize> {
+        let (x, y, z) = (
+            cube.0.wrapping_sub(self.min.0) as usize,
+            cube.1.wrapping_sub(self.min.1) as usize,
+            cube.2.wrapping_sub(self.min.2) as usize,
+        );
+        // Cubes below the minimum wrap around to huge indices
+        (x < self.size.0 && y < self.size.1 && z < self.size.2)
+            .then_some((x * self.size.1 + y) * self.size.2 + z)
+    }
+}
+
+impl Voxels for BitGrid {
+    fn new(min: Coord, max: Coord) -> Self {
+        let size = (
+            (max.0 - min.0 + 1) as usize,
+            (max.1 - min.1 + 1) as usize,
+            (max.2 - min.2 + 1) as usize,
+        );
+        Self {
+            min,
+            size,
+            bits: vec![0; (size.0 * size.1 * size.2).div_ceil(64)],
+        }
+    }
+
+    #[inline]
+    fn contains(&self, cube: Coord) -> bool {
+        self.index(cube)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
     #[inline]
-    fn is_outside(
+    fn insert(&mut self, cube: Coord) -> bool {
+        let i = self.index(cube).expect("cube out of bounds");
+        let (word, mask) = (&mut self.bits[i / 64], 1 << (i % 64));
+        let new = *word & mask == 0;
+        *word |= mask;
+        new
+    }
+}
+
+impl Space {
+    /// Bounding box of the cubes, with a layer of air around them.
+    fn around(cubes: &[Coord]) -> Self {
+        let min_x = cubes.iter().map(|c| c.0).min().unwrap();
+        let min_y = cubes.iter().map(|c| c.1).min().unwrap();
+        let min_z = cubes.iter().map(|c| c.2).min().unwrap();
+        let max_x = cubes.iter().map(|c| c.0).max().unwrap();
+        let max_y = cubes.iter().map(|c| c.1).max().unwrap();
+        let max_z = cubes.iter().map(|c| c.2).max().unwrap();
+        Self {
+            min: (min_x - 1, min_y - 1, min_z - 1),
+            max: (max_x + 1, max_y + 1, max_z + 1),
+        }
+    }
+
+    fn contains(&self, coords: Coord) -> bool {
+        (self.min.0..=self.max.0).contains(&coords.0)
+            && (self.min.1..=self.max.1).contains(&coords.1)
+            && (self.min.2..=self.max.2).contains(&coords.2)
+    }
+
+    fn cells(&self) -> impl Iterator<Item = Coord> + '_ {
+        (self.min.0..=self.max.0).flat_map(move |x| {
+            (self.min.1..=self.max.1)
+                .flat_map(move |y| (self.min.2..=self.max.2).map(move |z| (x, y, z)))
+        })
+    }
+
+    /// Collects the cells connected to `start` that satisfy `passable`, skipping
+    /// (and marking) the ones that were already visited.
+    fn fill<V: Voxels>(
         &self,
-        air: &FxHashSet<(isize, isize, isize)>,
-        cube: (isize, isize, isize),
-    ) -> bool {
-        !self.cubes.contains(&cube) && air.contains(&cube)
+        start: Coord,
+        visited: &mut V,
+        passable: impl Fn(Coord) -> bool,
+    ) -> Vec<Coord> {
+        let mut found = vec![];
+        let mut queue = VecDeque::new();
+        if visited.insert(start) {
+            queue.push_back(start);
+        }
+        while let Some(cube) = queue.pop_front() {
+            found.push(cube);
+            for next in neighbors(cube) {
+                if self.contains(next) && passable(next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        found
+    }
+}
+
+impl Droplet {
+    pub fn new(input: &[Cube]) -> Self {
+        Self::with_backend(input)
+    }
+}
+
+impl<V: Voxels> Droplet<V> {
+    pub fn with_backend(input: &[Cube]) -> Self {
+        let (cubes, space, lava) = lava::<V>(input);
+
+        let mut exterior = V::new(space.min, space.max);
+        space.fill(space.min, &mut exterior, |c| !lava.contains(c));
+
+        // Whatever air is left must be enclosed, so filling from it never reaches
+        // the exterior
+        let mut visited = V::new(space.min, space.max);
+        let mut pockets = vec![];
+        for cube in space.cells() {
+            if lava.contains(cube) || exterior.contains(cube) || visited.contains(cube) {
+                continue;
+            }
+            let mut pocket = space.fill(cube, &mut visited, |c| !lava.contains(c));
+            pocket.sort_unstable();
+            pockets.push(pocket);
+        }
+
+        Self {
+            cubes,
+            lava,
+            exterior,
+            pockets,
+            space,
+        }
+    }
+
+    /// Faces that are not touching other cubes, including the ones facing
+    /// air pockets.
+    pub fn surface_area(&self) -> usize {
+        count_faces(&self.cubes, |c| !self.lava.contains(c))
+    }
+
+    /// Faces that can be reached from outside the droplet.
+    pub fn exterior_area(&self) -> usize {
+        count_faces(&self.cubes, |c| self.exterior.contains(c))
+    }
+
+    /// Triangle mesh of the faces counted by [`Droplet::exterior_area`], or
+    /// [`Droplet::surface_area`] if faces around air pockets are included.
+    /// Each triangle comes with its outward normal.
+    fn triangles(&self, include_pockets: bool) -> Vec<([Coord; 3], Coord)> {
+        let faces: Box<dyn Iterator<Item = _>> = if include_pockets {
+            Box::new(exposed_faces(&self.cubes, |c| !self.lava.contains(c)))
+        } else {
+            Box::new(exposed_faces(&self.cubes, |c| self.exterior.contains(c)))
+        };
+        faces
+            .flat_map(|(cube, side)| {
+                let [a, b, c, d] = face_corners(cube, side);
+                let neighbor = neighbors(cube)[side];
+                let normal = (
+                    neighbor.0 - cube.0,
+                    neighbor.1 - cube.1,
+                    neighbor.2 - cube.2,
+                );
+                [([a, b, c], normal), ([a, c, d], normal)]
+            })
+            .collect()
+    }
+
+    /// Wavefront OBJ mesh of the droplet's surface.
+    pub fn to_obj(&self, include_pockets: bool) -> String {
+        let triangles = self.triangles(include_pockets);
+        let mut vertices: FxHashMap<Coord, usize> = FxHashMap::default();
+        let mut normals: FxHashMap<Coord, usize> = FxHashMap::default();
+        let (mut out, mut faces) = (String::from("o droplet\n"), String::new());
+        for (corners, normal) in triangles {
+            let next = normals.len() + 1;
+            let n = *normals.entry(normal).or_insert_with(|| {
+                writeln!(out, "vn {} {} {}", normal.0, normal.1, normal.2).unwrap();
+                next
+            });
+            faces.push('f');
+            for corner in corners {
+                let next = vertices.len() + 1;
+                let v = *vertices.entry(corner).or_insert_with(|| {
+                    writeln!(out, "v {} {} {}", corner.0, corner.1, corner.2).unwrap();
+                    next
+                });
+                write!(faces, " {v}//{n}").unwrap();
+            }
+            faces.push('\n');
+        }
+        out.push_str(&faces);
+        out
+    }
+
+    /// ASCII STL mesh of the droplet's surface.
+    pub fn to_stl(&self, include_pockets: bool) -> String {
+        let mut out = String::from("solid droplet\n");
+        for (corners, (nx, ny, nz)) in self.triangles(include_pockets) {
+            writeln!(out, "facet normal {nx} {ny} {nz}\n  outer loop").unwrap();
+            for (x, y, z) in corners {
+                writeln!(out, "    vertex {x} {y} {z}").unwrap();
+            }
+            writeln!(out, "  endloop\nendfacet").unwrap();
+        }
+        out.push_str("endsolid droplet\n");
+        out
+    }
+
+    /// Enclosed bodies of air, each sorted by coordinates.
+    pub fn pockets(&self) -> &[Vec<Coord>] {
+        &self.pockets
+    }
+
+    /// Number of cubes in the droplet, counting air pockets as filled.
+    pub fn volume(&self) -> usize {
+        self.cubes.len() + self.pockets.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Groups of lava cubes connected by their faces, each sorted by coordinates.
+    pub fn components(&self) -> Vec<Vec<Coord>> {
+        let mut cubes = self.cubes.clone();
+        cubes.sort_unstable();
+        let mut visited = V::new(self.space.min, self.space.max);
+        let mut components = vec![];
+        for cube in cubes {
+            if visited.contains(cube) {
+                continue;
+            }
+            let mut component = self
+                .space
+                .fill(cube, &mut visited, |c| self.lava.contains(c));
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+}
+
+/// Faces of the cubes whose neighbor is `exposed`, as the cube and the index of
+/// the neighbor in [`neighbors`].
+fn exposed_faces<'a>(
+    cubes: &'a [Coord],
+    exposed: impl Fn(Coord) -> bool + 'a,
+) -> impl Iterator<Item = (Coord, usize)> + 'a {
+    cubes.iter().flat_map(move |&cube| {
+        let sides = neighbors(cube).map(&exposed);
+        (0..6)
+            .filter(move |&side| sides[side])
+            .map(move |side| (cube, side))
+    })
+}
+
+fn count_faces(cubes: &[Coord], exposed: impl Fn(Coord) -> bool) -> usize {
+    exposed_faces(cubes, exposed).count()
+}
+
+/// Corners of a face, counter-clockwise when seen from outside the cube.
+fn face_corners((x, y, z): Coord, side: usize) -> [Coord; 4] {
+    match side {
+        0 => [(x, y, z), (x, y, z + 1), (x, y + 1, z + 1), (x, y + 1, z)],
+        1 => [
+            (x + 1, y, z),
+            (x + 1, y + 1, z),
+            (x + 1, y + 1, z + 1),
+            (x + 1, y, z + 1),
+        ],
+        2 => [(x, y, z), (x + 1, y, z), (x + 1, y, z + 1), (x, y, z + 1)],
+        3 => [
+            (x, y + 1, z),
+            (x, y + 1, z + 1),
+            (x + 1, y + 1, z + 1),
+            (x + 1, y + 1, z),
+        ],
+        4 => [(x, y, z), (x, y + 1, z), (x + 1, y + 1, z), (x + 1, y, z)],
+        5 => [
+            (x, y, z + 1),
+            (x + 1, y, z + 1),
+            (x + 1, y + 1, z + 1),
+            (x, y + 1, z + 1),
+        ],
+        _ => unreachable!(),
     }
 }
 
+fn neighbors(cube: Coord) -> [Coord; 6] {
+    [
+        (cube.0 - 1, cube.1, cube.2),
+        (cube.0 + 1, cube.1, cube.2),
+        (cube.0, cube.1 - 1, cube.2),
+        (cube.0, cube.1 + 1, cube.2),
+        (cube.0, cube.1, cube.2 - 1),
+        (cube.0, cube.1, cube.2 + 1),
+    ]
+}
+
+fn lava<V: Voxels>(input: &[Cube]) -> (Vec<Coord>, Space, V) {
+    let cubes = input
+        .iter()
+        .map(|&Cube { x, y, z }| (x, y, z))
+        .collect::<Vec<_>>();
+    let space = Space::around(&cubes);
+    let mut lava = V::new(space.min, space.max);
+    for &cube in &cubes {
+        lava.insert(cube);
+    }
+    (cubes, space, lava)
+}
+
 #[aoc_generator(day18)]
-fn parse(input: &str) -> Vec<Cube> {
+pub fn parse(input: &str) -> Vec<Cube> {
     input
         .lines()
         .map(|line| {
@@ -42,96 +378,23 @@ fn parse(input: &str) -> Vec<Cube> {
 }
 
 #[aoc(day18, part1)]
-pub fn part1(input: &[Cube]) -> i32 {
-    let cubes = input
-        .iter()
-        .map(|&Cube { x, y, z }| (x, y, z))
-        .collect::<FxHashSet<_>>();
-    input
-        .iter()
-        .map(|cube| {
-            (!cubes.contains(&(cube.x - 1, cube.y, cube.z)) as u8
-                + !cubes.contains(&(cube.x + 1, cube.y, cube.z)) as u8
-                + !cubes.contains(&(cube.x, cube.y - 1, cube.z)) as u8
-                + !cubes.contains(&(cube.x, cube.y + 1, cube.z)) as u8
-                + !cubes.contains(&(cube.x, cube.y, cube.z - 1)) as u8
-                + !cubes.contains(&(cube.x, cube.y, cube.z + 1)) as u8) as i32
-        })
-        .sum()
-}
-
-fn try_fill(cube: (isize, isize, isize), space: &Space<'_>) -> FxHashSet<(isize, isize, isize)> {
-    let mut visited: FxHashSet<(isize, isize, isize)> = FxHashSet::default();
-    let mut queue = VecDeque::new();
-    let mut air = FxHashSet::default();
-    queue.push_back(cube);
-    air.insert(cube);
-
-    while let Some(cube) = queue.pop_front() {
-        if !space.cubes.contains(&cube) && !visited.contains(&cube) {
-            air.insert(cube);
-
-            push(&mut queue, space, (cube.0 - 1, cube.1, cube.2));
-            push(&mut queue, space, (cube.0 + 1, cube.1, cube.2));
-            push(&mut queue, space, (cube.0, cube.1 - 1, cube.2));
-            push(&mut queue, space, (cube.0, cube.1 + 1, cube.2));
-            push(&mut queue, space, (cube.0, cube.1, cube.2 - 1));
-            push(&mut queue, space, (cube.0, cube.1, cube.2 + 1));
-        }
-        visited.insert(cube);
-    }
-    air
+pub fn part1(input: &[Cube]) -> usize {
+    let (cubes, _, lava) = lava::<BitGrid>(input);
+    count_faces(&cubes, |c| !lava.contains(c))
 }
 
-fn push(
-    queue: &mut VecDeque<(isize, isize, isize)>,
-    space: &Space<'_>,
-    coords: (isize, isize, isize),
-) {
-    if coords.0 >= space.min
-        && coords.0 <= space.max
-        && coords.1 >= space.min
-        && coords.1 <= space.max
-        && coords.2 >= space.min
-        && coords.2 <= space.max
-    {
-        queue.push_back(coords);
-    }
+pub fn part1_hash_set(input: &[Cube]) -> usize {
+    let (cubes, _, lava) = lava::<FxHashSet<_>>(input);
+    count_faces(&cubes, |c| !lava.contains(&c))
 }
 
 #[aoc(day18, part2)]
-pub fn part2(input: &[Cube]) -> i32 {
-    let cubes = input
-        .iter()
-        .map(|&Cube { x, y, z }| (x, y, z))
-        .collect::<FxHashSet<_>>();
-    let max_x = input.iter().map(|c| c.x).max().unwrap();
-    let max_y = input.iter().map(|c| c.y).max().unwrap();
-    let max_z = input.iter().map(|c| c.z).max().unwrap();
-    let min_x = input.iter().map(|c| c.x).min().unwrap();
-    let min_y = input.iter().map(|c| c.y).min().unwrap();
-    let min_z = input.iter().map(|c| c.z).min().unwrap();
-
-    let max = max_x.max(max_y).max(max_z) + 1;
-    let min = min_x.min(min_y).min(min_z) - 1;
-    let space = Space {
-        cubes: &cubes,
-        min,
-        max,
-    };
-    let air = try_fill((min, min, min), &space);
+pub fn part2(input: &[Cube]) -> usize {
+    Droplet::new(input).exterior_area()
+}
 
-    input
-        .iter()
-        .map(|cube| {
-            (space.is_outside(&air, (cube.x - 1, cube.y, cube.z)) as u8
-                + space.is_outside(&air, (cube.x + 1, cube.y, cube.z)) as u8
-                + space.is_outside(&air, (cube.x, cube.y - 1, cube.z)) as u8
-                + space.is_outside(&air, (cube.x, cube.y + 1, cube.z)) as u8
-                + space.is_outside(&air, (cube.x, cube.y, cube.z - 1)) as u8
-                + space.is_outside(&air, (cube.x, cube.y, cube.z + 1)) as u8) as i32
-        })
-        .sum()
+pub fn part2_hash_set(input: &[Cube]) -> usize {
+    Droplet::<FxHashSet<_>>::with_backend(input).exterior_area()
 }
 
 #[cfg(test)]
@@ -173,4 +436,85 @@ mod tests {
 2,3,5"#;
         assert_eq!(part2(&parse(input)), 58);
     }
+
+    #[test]
+    fn droplet_example() {
+        let input = r#"2,2,2
+1,2,2
+3,2,2
+2,1,2
+2,3,2
+2,2,1
+2,2,3
+2,2,4
+2,2,6
+1,2,5
+3,2,5
+2,1,5
+2,3,5"#;
+        let droplet = Droplet::new(&parse(input));
+        assert_eq!(droplet.surface_area(), 64);
+        assert_eq!(droplet.exterior_area(), 58);
+        assert_eq!(droplet.pockets(), [vec![(2, 2, 5)]]);
+        assert_eq!(droplet.volume(), 14);
+        let components = droplet.components();
+        assert_eq!(components.len(), 6);
+        assert_eq!(components.iter().map(Vec::len).max(), Some(8));
+    }
+
+    #[test]
+    fn backends_agree() {
+        let input = r#"2,2,2
+1,2,2
+3,2,2
+2,1,2
+2,3,2
+2,2,1
+2,2,3
+2,2,4
+2,2,6
+1,2,5
+3,2,5
+2,1,5
+2,3,5"#;
+        let input = parse(input);
+        assert_eq!(part1_hash_set(&input), 64);
+        assert_eq!(part2_hash_set(&input), 58);
+        let dense = Droplet::new(&input);
+        let sparse = Droplet::<FxHashSet<_>>::with_backend(&input);
+        assert_eq!(dense.pockets(), sparse.pockets());
+        assert_eq!(dense.components(), sparse.components());
+    }
+
+    #[test]
+    fn meshes() {
+        let cube = Droplet::new(&parse("1,1,1"));
+        let obj = cube.to_obj(false);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("vn ")).count(), 6);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 12);
+        assert!(obj.contains("vn 1 0 0\n"));
+        let stl = cube.to_stl(false);
+        assert!(stl.starts_with(
+            "solid droplet\nfacet normal -1 0 0\n  outer loop\n    vertex 1 1 1\n    vertex 1 1 2\n"
+        ));
+        assert_eq!(stl.matches("endfacet").count(), 12);
+
+        let input = r#"2,2,2
+1,2,2
+3,2,2
+2,1,2
+2,3,2
+2,2,1
+2,2,3
+2,2,4
+2,2,6
+1,2,5
+3,2,5
+2,1,5
+2,3,5"#;
+        let droplet = Droplet::new(&parse(input));
+        assert_eq!(droplet.to_stl(false).matches("endfacet").count(), 58 * 2);
+        assert_eq!(droplet.to_stl(true).matches("endfacet").count(), 64 * 2);
+    }
 }
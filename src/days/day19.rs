@@ -1,19 +1,63 @@
+use std::{fmt::Display, str::FromStr};
+
 use enum_map::{Enum, EnumMap};
 use enumset::{EnumSet, EnumSetType};
 use fxhash::FxHashSet;
 use rayon::prelude::*;
 
-pub struct Blueprint {
+const MAX_RESOURCES: usize = 16;
+
+type Amounts = [i32; MAX_RESOURCES];
+
+/// A recipe with the puzzle's four resources, for the search over explored
+/// inventories.
+struct Blueprint {
     costs: EnumMap<Resource, Vec<Cost>>,
     max_costs: EnumMap<Resource, i32>,
 }
 
+/// A production chain, where each robot collects one unit of a resource per
+/// minute and costs some amount of other resources.
+///
+/// Recipes are written in sentences, like the puzzle's blueprints:
+/// `Each <resource> robot costs <n> <resource> and <n> <resource>.` for every
+/// robot that can be built, optionally followed by `Start with <n> <resource> robot.`
+/// (by default, one robot for the first resource) and `Collect <resource> for <n> minutes.`
+/// (by default, the resource of the last robot for 24 minutes).
+#[derive(Clone, Debug)]
+pub struct Recipe {
+    names: Vec<String>,
+    // Cost of the robot that collects each resource, if it can be built
+    costs: Vec<Option<Vec<(usize, i32)>>>,
+    max_costs: Amounts,
+    robots: Amounts,
+    target: usize,
+    minutes: i32,
+}
+
+/// Problems with the text of a [`Recipe`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecipeError {
+    BadSentence(String),
+    BadNumber(String),
+    // Costs must be `<n> <resource>` pairs
+    BadCost(String),
+    TooManyResources,
+    NoRobots,
+}
+
 #[derive(Default, Debug, PartialEq, Eq, Hash, Clone)]
 struct Inventory {
     items: EnumMap<Resource, i32>,
     generators: EnumMap<Resource, i32>,
 }
 
+#[derive(Clone, Copy)]
+struct State {
+    items: Amounts,
+    robots: Amounts,
+}
+
 #[derive(Debug)]
 struct Cost(Resource, i32);
 
@@ -25,6 +69,23 @@ enum Resource {
     Obsidian,
 }
 
+/// Robots bought along the best branch of the search.
+pub struct Schedule<'a> {
+    recipe: &'a Recipe,
+    pub minutes: i32,
+    // Amount of the target resource at the end
+    pub collected: i32,
+    // Minute (starting from 1) during which each robot starts being built
+    pub purchases: Vec<(i32, usize)>,
+}
+
+struct Search {
+    max: i32,
+    // Robots bought along the current branch, with the minutes left at the time
+    purchases: Vec<(i32, usize)>,
+    best: Vec<(i32, usize)>,
+}
+
 #[derive(Default)]
 struct LastPurchase {
     afforded: EnumSet<Resource>,
@@ -32,6 +93,42 @@ struct LastPurchase {
 }
 
 impl Blueprint {
+    /// Converts a recipe that only uses ore, clay, obsidian and geodes, starts
+    /// with one ore robot and opens geodes.
+    fn from_recipe(recipe: &Recipe) -> Option<Self> {
+        let resource = |r: usize| match recipe.names[r].as_str() {
+            "ore" => Some(Resource::Ore),
+            "clay" => Some(Resource::Clay),
+            "obsidian" => Some(Resource::Obsidian),
+            "geode" => Some(Resource::Geode),
+            _ => None,
+        };
+        let resources = (0..recipe.names.len())
+            .map(resource)
+            .collect::<Option<Vec<_>>>()?;
+        let start = |r: usize| (resources[r] == Resource::Ore) as i32;
+        if resources[recipe.target] != Resource::Geode
+            || !resources.contains(&Resource::Ore)
+            || recipe.costs.iter().any(Option::is_none)
+            || (0..resources.len()).any(|r| recipe.robots[r] != start(r))
+        {
+            return None;
+        }
+        let mut costs: EnumMap<Resource, Vec<Cost>> = EnumMap::default();
+        for (robot, robot_costs) in recipe.costs.iter().enumerate() {
+            costs[resources[robot]] = robot_costs
+                .iter()
+                .flatten()
+                .map(|&(r, amount)| Cost(resources[r], amount))
+                .collect();
+        }
+        let mut max_costs: EnumMap<Resource, i32> = EnumMap::default();
+        for cost in costs.values().flatten() {
+            max_costs[cost.0] = max_costs[cost.0].max(cost.1);
+        }
+        Some(Self { costs, max_costs })
+    }
+
     fn affordable_by(&self, inventory: &Inventory) -> EnumSet<Resource> {
         self.costs
             .iter()
@@ -131,6 +228,310 @@ impl Blueprint {
     }
 }
 
+impl Recipe {
+    pub fn resource_name(&self, resource: usize) -> &str {
+        &self.names[resource]
+    }
+
+    fn resource(&mut self, name: &str) -> Result<usize, RecipeError> {
+        match self.names.iter().position(|n| n == name) {
+            Some(i) => Ok(i),
+            None if self.names.len() == MAX_RESOURCES => Err(RecipeError::TooManyResources),
+            None => {
+                self.names.push(name.to_string());
+                self.costs.push(None);
+                Ok(self.names.len() - 1)
+            }
+        }
+    }
+
+    /// Minutes to wait until the robot can be bought with the current robots,
+    /// or `None` if some resource is never produced.
+    fn minutes_until_affordable(&self, state: &State, costs: &[(usize, i32)]) -> Option<i32> {
+        let mut wait = 0;
+        for &(resource, amount) in costs {
+            let missing = amount - state.items[resource];
+            if missing > 0 {
+                let rate = state.robots[resource];
+                if rate == 0 {
+                    return None;
+                }
+                wait = wait.max((missing + rate - 1) / rate);
+            }
+        }
+        Some(wait)
+    }
+
+    /// Target resources we'd get in a relaxed problem, where several robots can
+    /// be built at once and each robot type has its own copy of the resources
+    /// to spend.
+    fn upper_bound(&self, state: &State, minutes: i32) -> i32 {
+        let n = self.names.len();
+        let mut copies = [state.items; MAX_RESOURCES];
+        let mut robots = state.robots;
+        let mut collected = state.items[self.target];
+        for _ in 0..minutes {
+            let mut built = [false; MAX_RESOURCES];
+            for (robot, items) in copies.iter_mut().enumerate().take(n) {
+                let Some(costs) = &self.costs[robot] else {
+                    continue;
+                };
+                if costs.iter().all(|&(r, amount)| items[r] >= amount) {
+                    for &(r, amount) in costs {
+                        items[r] -= amount;
+                    }
+                    built[robot] = true;
+                }
+            }
+            for items in copies.iter_mut().take(n) {
+                for r in 0..n {
+                    items[r] += robots[r];
+                }
+            }
+            collected += robots[self.target];
+            for r in 0..n {
+                robots[r] += built[r] as i32;
+            }
+        }
+        collected
+    }
+
+    /// Branches on which robot to buy next, waiting for it to be affordable.
+    fn search_next_robot(&self, state: &State, minutes: i32, search: &mut Search) {
+        let collected = state.items[self.target] + state.robots[self.target] * minutes;
+        if collected > search.max {
+            search.max = collected;
+            search.best.clone_from(&search.purchases);
+        }
+        if self.upper_bound(state, minutes) <= search.max {
+            return;
+        }
+        // Try the target first, then the robots that are further down the chain
+        let order = std::iter::once(self.target)
+            .chain((0..self.names.len()).rev().filter(|&r| r != self.target));
+        for robot in order {
+            let Some(costs) = &self.costs[robot] else {
+                continue;
+            };
+            // We can only spend so much in a minute
+            if robot != self.target && state.robots[robot] >= self.max_costs[robot] {
+                continue;
+            }
+            let Some(wait) = self.minutes_until_affordable(state, costs) else {
+                continue;
+            };
+            // The robot must be ready before the last minute to be of any use
+            if wait + 1 >= minutes {
+                continue;
+            }
+            let mut next = *state;
+            for r in 0..self.names.len() {
+                next.items[r] += state.robots[r] * (wait + 1);
+            }
+            for &(r, amount) in costs {
+                next.items[r] -= amount;
+            }
+            next.robots[robot] += 1;
+            search.purchases.push((minutes - wait, robot));
+            self.search_next_robot(&next, minutes - wait - 1, search);
+            search.purchases.pop();
+        }
+    }
+
+    /// Finds the purchases that collect the most of the target resource.
+    pub fn best_schedule(&self) -> Schedule<'_> {
+        self.best_schedule_for(self.minutes)
+    }
+
+    /// Same as [`Recipe::best_schedule`], in a different amount of time.
+    pub fn best_schedule_for(&self, minutes: i32) -> Schedule<'_> {
+        let state = State {
+            items: [0; MAX_RESOURCES],
+            robots: self.robots,
+        };
+        let mut search = Search {
+            max: 0,
+            purchases: vec![],
+            best: vec![],
+        };
+        self.search_next_robot(&state, minutes, &mut search);
+        Schedule {
+            recipe: self,
+            minutes,
+            collected: search.max,
+            purchases: search
+                .best
+                .into_iter()
+                .map(|(left, resource)| (minutes - left + 1, resource))
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Recipe {
+    type Err = RecipeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut recipe = Recipe {
+            names: vec![],
+            costs: vec![],
+            max_costs: [0; MAX_RESOURCES],
+            robots: [0; MAX_RESOURCES],
+            target: 0,
+            minutes: 24,
+        };
+        let number = |word: &str| {
+            word.parse()
+                .map_err(|_| RecipeError::BadNumber(word.to_string()))
+        };
+        let (mut target, mut start) = (None, vec![]);
+        let mut last_robot = None;
+        // Skip the "Blueprint <n>:" header, if any
+        let s = match s.split_once(':') {
+            Some((header, rest)) if header.starts_with("Blueprint") => rest,
+            _ => s,
+        };
+        for sentence in s.split('.').map(str::trim).filter(|s| !s.is_empty()) {
+            let words = sentence.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                ["Each", robot, "robot", "costs", costs @ ..] => {
+                    let robot = recipe.resource(robot)?;
+                    let costs = costs.iter().filter(|&&w| w != "and").collect::<Vec<_>>();
+                    let costs = costs
+                        .chunks(2)
+                        .map(|cost| match cost {
+                            [amount, name] => Ok((
+                                recipe.resource(name.trim_end_matches(','))?,
+                                number(amount)?,
+                            )),
+                            c => Err(RecipeError::BadCost(c.iter().map(|w| **w).collect())),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    recipe.costs[robot] = Some(costs);
+                    last_robot = Some(robot);
+                }
+                ["Start", "with", amount, robot, "robot" | "robots"] => {
+                    start.push((recipe.resource(robot)?, number(amount)?));
+                }
+                ["Collect", name, "for", minutes, "minutes" | "minute"] => {
+                    target = Some(recipe.resource(name)?);
+                    recipe.minutes = number(minutes)?;
+                }
+                _ => return Err(RecipeError::BadSentence(sentence.to_string())),
+            }
+        }
+        let first = recipe.costs.iter().position(Option::is_some);
+        let (Some(target), Some(first)) = (target.or(last_robot), first) else {
+            return Err(RecipeError::NoRobots);
+        };
+        recipe.target = target;
+        if start.is_empty() {
+            start.push((first, 1));
+        }
+        for (robot, amount) in start {
+            recipe.robots[robot] += amount;
+        }
+        for &(resource, amount) in recipe.costs.iter().flatten().flatten() {
+            recipe.max_costs[resource] = recipe.max_costs[resource].max(amount);
+        }
+        Ok(recipe)
+    }
+}
+
+impl Display for RecipeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadSentence(sentence) => write!(f, "invalid sentence {sentence:?}"),
+            Self::BadNumber(word) => write!(f, "invalid number {word:?}"),
+            Self::BadCost(cost) => write!(f, "invalid cost {cost:?}"),
+            Self::TooManyResources => write!(f, "more than {MAX_RESOURCES} resources"),
+            Self::NoRobots => write!(f, "no robots"),
+        }
+    }
+}
+
+impl std::error::Error for RecipeError {}
+
+/// Minute-by-minute account of the schedule, in the puzzle's words.
+impl Display for Schedule<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let recipe = self.recipe;
+        let robot_name = |r: usize| {
+            // Geodes are cracked rather than collected
+            if recipe.names[r] == "geode" {
+                "geode-cracking robot".to_string()
+            } else {
+                format!("{}-collecting robot", recipe.names[r])
+            }
+        };
+        let mut items = [0; MAX_RESOURCES];
+        let mut robots = recipe.robots;
+        for minute in 1..=self.minutes {
+            if minute > 1 {
+                writeln!(f)?;
+            }
+            writeln!(f, "== Minute {minute} ==")?;
+            let purchase = self
+                .purchases
+                .iter()
+                .find(|(m, _)| *m == minute)
+                .map(|(_, r)| *r);
+            if let Some(robot) = purchase {
+                let costs = recipe.costs[robot].as_ref().unwrap();
+                let spent = costs
+                    .iter()
+                    .map(|&(r, amount)| format!("{amount} {}", recipe.names[r]))
+                    .collect::<Vec<_>>()
+                    .join(" and ");
+                let name = robot_name(robot);
+                let article = if name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+                    "an"
+                } else {
+                    "a"
+                };
+                writeln!(f, "Spend {spent} to start building {article} {name}.")?;
+                for &(r, amount) in costs {
+                    items[r] -= amount;
+                }
+            }
+            for r in 0..recipe.names.len() {
+                let count = robots[r];
+                if count == 0 {
+                    continue;
+                }
+                items[r] += count;
+                let (total, name) = (items[r], &recipe.names[r]);
+                let (s, verb) = if count == 1 { ("", "s") } else { ("s", "") };
+                if name == "geode" {
+                    let total_s = if total == 1 { "" } else { "s" };
+                    writeln!(
+                        f,
+                        "{count} {}{s} crack{verb} {count} geode{s}; \
+                         you now have {total} open geode{total_s}.",
+                        robot_name(r)
+                    )?;
+                } else {
+                    writeln!(
+                        f,
+                        "{count} {}{s} collect{verb} {count} {name}; you now have {total} {name}.",
+                        robot_name(r)
+                    )?;
+                }
+            }
+            if let Some(robot) = purchase {
+                robots[robot] += 1;
+                writeln!(
+                    f,
+                    "The new {} is ready; you now have {} of them.",
+                    robot_name(robot),
+                    robots[robot]
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Inventory {
     fn generate(&mut self, generators: &EnumMap<Resource, i32>) {
         for (res, amt) in generators {
@@ -144,49 +545,42 @@ impl Inventory {
 }
 
 #[aoc_generator(day19)]
-fn parse(input: &str) -> Vec<Blueprint> {
+pub fn parse(input: &str) -> Vec<Recipe> {
     input
         .lines()
-        .map(|line| {
-            let numbers = line
-                .split_whitespace()
-                .flat_map(|w| w.parse::<i32>())
-                .collect::<Vec<_>>();
-            let costs: EnumMap<Resource, Vec<Cost>> = [
-                (Resource::Ore, vec![Cost(Resource::Ore, numbers[0])]),
-                (Resource::Clay, vec![Cost(Resource::Ore, numbers[1])]),
-                (
-                    Resource::Obsidian,
-                    vec![
-                        Cost(Resource::Ore, numbers[2]),
-                        Cost(Resource::Clay, numbers[3]),
-                    ],
-                ),
-                (
-                    Resource::Geode,
-                    vec![
-                        Cost(Resource::Ore, numbers[4]),
-                        Cost(Resource::Obsidian, numbers[5]),
-                    ],
-                ),
-            ]
-            .into_iter()
-            .collect();
-            let mut max_costs: EnumMap<Resource, i32> = EnumMap::default();
-            for cost in costs.values().flatten() {
-                max_costs[cost.0] = max_costs[cost.0].max(cost.1);
-            }
-            Blueprint { max_costs, costs }
-        })
+        .map(|line| line.parse().unwrap_or_else(|e| panic!("{e}")))
         .collect()
 }
 
 #[aoc(day19, part1)]
-pub fn part1(input: &[Blueprint]) -> i32 {
+pub fn part1(input: &[Recipe]) -> i32 {
     input
         .par_iter()
         .enumerate()
-        .map(|(i, blueprint)| {
+        .map(|(i, recipe)| (i as i32 + 1) * recipe.best_schedule_for(24).collected)
+        .sum()
+}
+
+#[aoc(day19, part2)]
+pub fn part2(input: &[Recipe]) -> i32 {
+    input
+        .par_iter()
+        .take(3)
+        .map(|recipe| recipe.best_schedule_for(32).collected)
+        .product()
+}
+
+fn blueprint(recipe: &Recipe) -> Blueprint {
+    Blueprint::from_recipe(recipe)
+        .expect("the explored search only supports the puzzle's resources")
+}
+
+pub fn part1_explored(input: &[Recipe]) -> i32 {
+    input
+        .par_iter()
+        .enumerate()
+        .map(|(i, recipe)| {
+            let blueprint = blueprint(recipe);
             let mut inventory = Inventory::default();
             inventory.generators[Resource::Ore] = 1;
             let mut memo = FxHashSet::default();
@@ -197,12 +591,12 @@ pub fn part1(input: &[Blueprint]) -> i32 {
         .sum()
 }
 
-#[aoc(day19, part2)]
-pub fn part2(input: &[Blueprint]) -> i32 {
+pub fn part2_explored(input: &[Recipe]) -> i32 {
     input
         .par_iter()
         .take(3)
-        .map(|blueprint| {
+        .map(|recipe| {
+            let blueprint = blueprint(recipe);
             let mut inventory = Inventory::default();
             inventory.generators[Resource::Ore] = 1;
             let mut memo =
@@ -231,4 +625,116 @@ Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsid
 Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian."#;
         assert_eq!(part2(&parse(input)), 56 * 62);
     }
+
+    #[test]
+    fn schedule_example() {
+        let input = r#"Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian."#;
+        let recipes = parse(input);
+        let schedule = recipes[0].best_schedule_for(24);
+        assert_eq!(schedule.collected, 9);
+        let text = schedule.to_string();
+        assert!(text.starts_with(
+            "== Minute 1 ==\n1 ore-collecting robot collects 1 ore; you now have 1 ore.\n\n"
+        ));
+        assert!(text.contains(
+            "Spend 2 ore to start building a clay-collecting robot.\n\
+             1 ore-collecting robot collects 1 ore; you now have 1 ore.\n\
+             The new clay-collecting robot is ready; you now have 1 of them.\n"
+        ));
+        assert!(text.ends_with("you now have 9 open geodes.\n"));
+        assert_eq!(
+            text.matches("to start building").count(),
+            schedule.purchases.len()
+        );
+    }
+
+    #[test]
+    fn explored_example() {
+        let input = r#"Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
+Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian."#;
+        assert_eq!(part1_explored(&parse(input)), 33);
+    }
+
+    /// Tries every purchase at every minute.
+    fn brute_force(recipe: &Recipe, state: State, minutes: i32) -> i32 {
+        let mut produced = state;
+        for r in 0..recipe.names.len() {
+            produced.items[r] += state.robots[r];
+        }
+        if minutes == 1 {
+            return produced.items[recipe.target];
+        }
+        let mut best = brute_force(recipe, produced, minutes - 1);
+        for (robot, costs) in recipe.costs.iter().enumerate() {
+            let Some(costs) = costs else { continue };
+            if costs.iter().all(|&(r, amount)| state.items[r] >= amount) {
+                let mut next = produced;
+                for &(r, amount) in costs {
+                    next.items[r] -= amount;
+                }
+                next.robots[robot] += 1;
+                best = best.max(brute_force(recipe, next, minutes - 1));
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn custom_recipes() {
+        let recipes = [
+            "Each wood robot costs 1 wood. Each plank robot costs 2 wood. \
+             Each chair robot costs 3 plank and 1 wood. Collect chair for 12 minutes.",
+            "Each a robot costs 2 b. Each b robot costs 1 a. \
+             Start with 1 a robot. Start with 1 b robot. Collect a for 10 minutes.",
+            "Each ore robot costs 2 ore. Each clay robot costs 2 ore. \
+             Each gem robot costs 2 ore, 3 clay and 1 gem. Start with 2 gem robots. \
+             Collect gem for 10 minutes.",
+        ];
+        for text in recipes {
+            let recipe: Recipe = text.parse().unwrap();
+            let state = State {
+                items: [0; MAX_RESOURCES],
+                robots: recipe.robots,
+            };
+            let schedule = recipe.best_schedule();
+            assert_eq!(
+                schedule.collected,
+                brute_force(&recipe, state, recipe.minutes),
+                "{text}"
+            );
+        }
+
+        let error = |s: &str| s.parse::<Recipe>().err();
+        assert_eq!(
+            error("Each a robot costs 1 a. Buy more"),
+            Some(RecipeError::BadSentence("Buy more".into()))
+        );
+        assert_eq!(
+            error("Each a robot costs x a"),
+            Some(RecipeError::BadNumber("x".into()))
+        );
+        assert_eq!(
+            error("Each a robot costs 1 a and 2"),
+            Some(RecipeError::BadCost("2".into()))
+        );
+        assert_eq!(
+            error("Collect a for 3 minutes"),
+            Some(RecipeError::NoRobots)
+        );
+        let many = (0..=MAX_RESOURCES)
+            .map(|i| format!("Each r{i} robot costs 1 r0."))
+            .collect::<String>();
+        assert_eq!(error(&many), Some(RecipeError::TooManyResources));
+
+        let recipe: Recipe = recipes[0].parse().unwrap();
+        assert!(Blueprint::from_recipe(&recipe).is_none());
+        // The generator accepts any recipe, in the puzzle's default time
+        assert_eq!(
+            part1(&parse(recipes[0])),
+            recipe.best_schedule_for(24).collected
+        );
+        assert_eq!(recipe.resource_name(recipe.target), "chair");
+        let text = recipe.best_schedule().to_string();
+        assert!(text.contains("to start building a plank-collecting robot."));
+    }
 }
@@ -1,12 +1,207 @@
 use std::collections::VecDeque;
 
+/// Circular list of numbers that can be mixed, split into blocks of about
+/// `sqrt(n)` numbers so that each move only shifts one block.
+#[derive(Clone, Debug)]
+pub struct MixedList {
+    numbers: Vec<i64>,
+    // Indices into `numbers`, in their current order
+    blocks: Vec<Vec<usize>>,
+    block_of: Vec<usize>,
+    block_size: usize,
+    // Moves until the blocks are rebalanced
+    moves_left: usize,
+}
+
+impl MixedList {
+    pub fn new(numbers: Vec<i64>) -> Self {
+        let block_size = ((numbers.len() as f64).sqrt() as usize).max(1);
+        let mut list = Self {
+            block_of: vec![0; numbers.len()],
+            numbers,
+            blocks: vec![],
+            block_size,
+            moves_left: 0,
+        };
+        let order = (0..list.numbers.len()).collect();
+        list.rebuild(order);
+        list
+    }
+
+    fn rebuild(&mut self, order: Vec<usize>) {
+        self.blocks = order
+            .chunks(self.block_size)
+            .map(<[usize]>::to_vec)
+            .collect();
+        for (b, block) in self.blocks.iter().enumerate() {
+            for &id in block {
+                self.block_of[id] = b;
+            }
+        }
+        self.moves_left = self.block_size;
+    }
+
+    fn order(&self) -> Vec<usize> {
+        self.blocks.concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.numbers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.numbers.is_empty()
+    }
+
+    /// Current position of the number originally at index `id`.
+    fn position(&self, id: usize) -> usize {
+        let b = self.block_of[id];
+        let before: usize = self.blocks[..b].iter().map(Vec::len).sum();
+        before + self.blocks[b].iter().position(|&i| i == id).unwrap()
+    }
+
+    fn insert(&mut self, mut pos: usize, id: usize) {
+        let last = self.blocks.len() - 1;
+        for (b, block) in self.blocks.iter_mut().enumerate() {
+            if pos <= block.len() && (pos < block.len() || b == last) {
+                block.insert(pos, id);
+                self.block_of[id] = b;
+                return;
+            }
+            pos -= block.len();
+        }
+        unreachable!()
+    }
+
+    /// Moves the number originally at index `id` by `offset` places.
+    fn shift(&mut self, id: usize, offset: i64) {
+        let len = self.len() as i64;
+        if len < 2 {
+            return;
+        }
+        let pos = self.position(id);
+        let b = self.block_of[id];
+        let in_block = self.blocks[b].iter().position(|&i| i == id).unwrap();
+        self.blocks[b].remove(in_block);
+        // The number is moving among the other len - 1 numbers
+        let dest = (pos as i64 + offset).rem_euclid(len - 1);
+        self.insert(dest as usize, id);
+
+        self.moves_left -= 1;
+        if self.moves_left == 0 {
+            self.rebuild(self.order());
+        }
+    }
+
+    /// Moves the number originally at index `id` forward or backward by its value.
+    pub fn move_number(&mut self, id: usize) {
+        self.shift(id, self.numbers[id]);
+    }
+
+    /// Undoes [`MixedList::move_number`]. The list ends up rotated compared to
+    /// before the move when the number was last.
+    pub fn unmove_number(&mut self, id: usize) {
+        self.shift(id, -self.numbers[id]);
+    }
+
+    /// Moves every number once, in their original order.
+    pub fn mix(&mut self) {
+        for id in 0..self.len() {
+            self.move_number(id);
+        }
+    }
+
+    /// Undoes [`MixedList::mix`], up to a rotation of the list.
+    pub fn unmix(&mut self) {
+        for id in (0..self.len()).rev() {
+            self.unmove_number(id);
+        }
+    }
+
+    /// The numbers in their current order, starting from the same place as the
+    /// original list.
+    pub fn sequence(&self) -> Vec<i64> {
+        self.blocks
+            .iter()
+            .flatten()
+            .map(|&id| self.numbers[id])
+            .collect()
+    }
+
+    /// The number `offset` places after the 0, wrapping around.
+    pub fn after_zero(&self, offset: usize) -> i64 {
+        let zero = self.numbers.iter().position(|&n| n == 0).expect("no 0");
+        let mut pos = (self.position(zero) + offset) % self.len();
+        for block in &self.blocks {
+            if pos < block.len() {
+                return self.numbers[block[pos]];
+            }
+            pos -= block.len();
+        }
+        unreachable!()
+    }
+}
+
+/// How to mix a list of numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mixer {
+    // Multiplied with every number before mixing
+    pub key: i64,
+    pub rounds: usize,
+}
+
+impl Mixer {
+    pub const DECRYPTION: Mixer = Mixer {
+        key: 811589153,
+        rounds: 10,
+    };
+
+    pub const GROVE_OFFSETS: [usize; 3] = [1000, 2000, 3000];
+
+    pub fn mix(&self, numbers: &[i32]) -> MixedList {
+        let mut list = MixedList::new(numbers.iter().map(|&n| n as i64 * self.key).collect());
+        for _ in 0..self.rounds {
+            list.mix();
+        }
+        list
+    }
+
+    /// Undoes [`Mixer::mix`], giving back the numbers with the key applied.
+    pub fn unmix(&self, list: &mut MixedList) {
+        for _ in 0..self.rounds {
+            list.unmix();
+        }
+    }
+
+    /// Sum of the numbers at each offset after the 0, once mixed.
+    pub fn coordinates(&self, numbers: &[i32], offsets: &[usize]) -> i64 {
+        let list = self.mix(numbers);
+        offsets.iter().map(|&offset| list.after_zero(offset)).sum()
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self { key: 1, rounds: 1 }
+    }
+}
+
 #[aoc_generator(day20)]
-fn parse(input: &str) -> Vec<i32> {
+pub fn parse(input: &str) -> Vec<i32> {
     input.lines().map(|l| l.parse().unwrap()).collect()
 }
 
 #[aoc(day20, part1)]
-pub fn part1(input: &[i32]) -> i32 {
+pub fn part1(input: &[i32]) -> i64 {
+    Mixer::default().coordinates(input, &Mixer::GROVE_OFFSETS)
+}
+
+#[aoc(day20, part2)]
+pub fn part2(input: &[i32]) -> i64 {
+    Mixer::DECRYPTION.coordinates(input, &Mixer::GROVE_OFFSETS)
+}
+
+pub fn part1_vec_deque(input: &[i32]) -> i32 {
     let mut buffer = input
         .iter()
         .enumerate()
@@ -37,8 +232,7 @@ pub fn part1(input: &[i32]) -> i32 {
         .sum()
 }
 
-#[aoc(day20, part2)]
-pub fn part2(input: &[i32]) -> i64 {
+pub fn part2_vec_deque(input: &[i32]) -> i64 {
     let mut buffer = input
         .iter()
         .enumerate()
@@ -72,6 +266,7 @@ pub fn part2(input: &[i32]) -> i64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::random::Random;
 
     #[test]
     fn part1_example() {
@@ -96,4 +291,86 @@ mod tests {
 4"#;
         assert_eq!(part2(&parse(input)), 1623178306);
     }
+
+    /// Rotates a circular sequence so that it starts at 0, to compare it with
+    /// the puzzle's states regardless of where they start.
+    fn from_zero(sequence: &[i64]) -> Vec<i64> {
+        let zero = sequence.iter().position(|&n| n == 0).unwrap();
+        let mut sequence = sequence.to_vec();
+        sequence.rotate_left(zero);
+        sequence
+    }
+
+    #[test]
+    fn mix_example() {
+        let mut list = MixedList::new(vec![1, 2, -3, 3, -2, 0, 4]);
+        let states: [[i64; 7]; 7] = [
+            [2, 1, -3, 3, -2, 0, 4],
+            [1, -3, 2, 3, -2, 0, 4],
+            [1, 2, 3, -2, -3, 0, 4],
+            [1, 2, -2, -3, 0, 3, 4],
+            [1, 2, -3, 0, 3, 4, -2],
+            [1, 2, -3, 0, 3, 4, -2],
+            [1, 2, -3, 4, 0, 3, -2],
+        ];
+        for (id, state) in states.iter().enumerate() {
+            list.move_number(id);
+            assert_eq!(from_zero(&list.sequence()), from_zero(state));
+        }
+
+        let key = 811589153;
+        let mut list = MixedList::new([1, 2, -3, 3, -2, 0, 4].map(|n| n * key).to_vec());
+        list.mix();
+        assert_eq!(
+            from_zero(&list.sequence()),
+            [0, -3, 4, -2, 3, 2, 1].map(|n| n * key)
+        );
+    }
+
+    #[test]
+    fn implementations_agree() {
+        let mut random = Random::new(0x2022_1220);
+        let input = (0..500)
+            .map(|_| random.range(-10000, 10000) as i32)
+            .filter(|&n| n != 0)
+            .chain([0])
+            .collect::<Vec<_>>();
+        assert_eq!(part1(&input), part1_vec_deque(&input) as i64);
+        assert_eq!(part2(&input), part2_vec_deque(&input));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut random = Random::new(0x2022_1220);
+        let input = (0..300)
+            .map(|_| random.range(-1000, 1000) as i32)
+            .filter(|&n| n != 0)
+            .chain([0])
+            .collect::<Vec<_>>();
+        for mixer in [
+            Mixer::default(),
+            Mixer::DECRYPTION,
+            Mixer { key: -7, rounds: 3 },
+        ] {
+            let original = input
+                .iter()
+                .map(|&n| n as i64 * mixer.key)
+                .collect::<Vec<_>>();
+            let mut list = mixer.mix(&input);
+            assert_ne!(from_zero(&list.sequence()), from_zero(&original));
+            mixer.unmix(&mut list);
+            assert_eq!(from_zero(&list.sequence()), from_zero(&original));
+        }
+
+        let mixer = Mixer { key: 3, rounds: 2 };
+        let list = mixer.mix(&[1, 2, -3, 3, -2, 0, 4]);
+        let sequence = from_zero(&list.sequence());
+        for offset in 0..20 {
+            assert_eq!(list.after_zero(offset), sequence[offset % 7]);
+        }
+        assert_eq!(
+            mixer.coordinates(&[1, 2, -3, 3, -2, 0, 4], &[1, 2]),
+            sequence[1] + sequence[2]
+        );
+    }
 }
@@ -1,6 +1,12 @@
-use std::{convert::Infallible, fmt::Debug, str::FromStr};
+use std::{
+    collections::hash_map,
+    fmt::{Debug, Display, Write},
+    str::FromStr,
+};
 
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::util::bigint::BigInt;
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Key(u32);
@@ -21,38 +27,233 @@ pub struct Entry {
     instruction: Instruction,
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Expression computed by a monkey, with the monkeys it depends on inlined.
+/// Nodes are stored after their operands and the last one is the whole
+/// expression, so that nothing needs to recurse over long chains.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Expr {
+    nodes: Vec<Node>,
+    // Whether each node depends on the human
+    human: Vec<bool>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Node {
+    Human(Key),
+    Constant(i64),
+    // Operands are indices of earlier nodes
+    Binary {
+        key: Key,
+        op: Op,
+        lhs: usize,
+        rhs: usize,
+    },
+}
+
+// An operand while folding, constants are only stored if they're still needed
+#[derive(Clone, Copy)]
+enum Folded {
+    Constant(i64),
+    Node(usize),
+}
+
+// What is left to print of an expression
+enum Piece {
+    Node(usize),
+    Op(Op),
+    Text(&'static str),
+}
+
+/// Exact fraction, always reduced and with a positive denominator.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+/// `humn * a + b`, the value of an expression with at most one unknown.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+struct Linear {
+    a: Rational,
+    b: Rational,
+}
+
+/// Why the monkeys' numbers can't be computed.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum SolveError {
+    Program(ProgramError),
+    // Two expressions depending on `humn` are multiplied, or `humn` is a divisor
+    NonLinear(Key),
+    DivisionByZero(Key),
+    Overflow(Key),
+    // Both sides of `root` are equal for every value of `humn`
+    AnySolution,
+    NoSolution,
+    NotInteger(Rational),
+}
+
+/// Problems with a list of monkeys, found before running it.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum ProgramError {
+    // Names must be four ASCII letters or digits
+    BadName(String),
+    BadLine(String),
+    Undefined { key: Key, used_by: Key },
+    Duplicate(Key),
+    // `root` and `humn` must always be defined
+    Missing(Key),
+    // `root` must compare two monkeys
+    NotOperation(Key),
+    // Monkeys that depend on each other, in order
+    Cycle(Vec<Key>),
+}
+
+/// Result of a monkey, which only needs a big integer if it doesn't fit in
+/// an `i64`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Value {
+    Small(i64),
+    Big(BigInt),
+}
+
+/// Operation that would have overflowed if done on `i64`s.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Overflow {
+    pub key: Key,
+    pub op: Op,
+    pub lhs: i64,
+    pub rhs: i64,
+}
+
+pub struct Evaluation {
+    pub value: Value,
+    // In evaluation order
+    pub overflows: Vec<Overflow>,
+}
+
 #[derive(Default)]
 struct Memory {
-    variables: FxHashMap<Key, i64>,
+    variables: FxHashMap<Key, Value>,
+    overflows: Vec<Overflow>,
 }
 
 impl Memory {
-    fn run(&mut self, instruction: Entry) {
+    fn run(&mut self, instruction: Entry) -> Result<(), SolveError> {
         if self.variables.contains_key(&instruction.key) {
-            return;
+            return Ok(());
         }
-        let result = match instruction.instruction {
-            Instruction::Immediate(imm) => imm,
-            Instruction::Add(x, y) => self.get(x) + self.get(y),
-            Instruction::Sub(x, y) => self.get(x) - self.get(y),
-            Instruction::Mul(x, y) => self.get(x) * self.get(y),
-            Instruction::Div(x, y) => self.get(x) / self.get(y),
-            Instruction::Input => return,
+        let result = match instruction.instruction.operands() {
+            Some((op, x, y)) => self.apply(instruction.key, op, x, y)?,
+            None => match instruction.instruction {
+                Instruction::Immediate(imm) => Value::Small(imm),
+                _ => return Ok(()),
+            },
         };
         self.variables.insert(instruction.key, result);
+        Ok(())
+    }
+
+    /// Computes with `i64`s, and falls back to big integers on overflow.
+    fn apply(&mut self, key: Key, op: Op, x: Key, y: Key) -> Result<Value, SolveError> {
+        let (lhs, rhs) = (self.get(x).clone(), self.get(y).clone());
+        if rhs == Value::Small(0) && op == Op::Div {
+            return Err(SolveError::DivisionByZero(key));
+        }
+        if let (&Value::Small(lhs), &Value::Small(rhs)) = (&lhs, &rhs) {
+            let result = match op {
+                Op::Add => lhs.checked_add(rhs),
+                Op::Sub => lhs.checked_sub(rhs),
+                Op::Mul => lhs.checked_mul(rhs),
+                Op::Div => lhs.checked_div(rhs),
+            };
+            if let Some(result) = result {
+                return Ok(Value::Small(result));
+            }
+            self.overflows.push(Overflow { key, op, lhs, rhs });
+        }
+        let (lhs, rhs) = (lhs.to_big(), rhs.to_big());
+        let result = match op {
+            Op::Add => &lhs + &rhs,
+            Op::Sub => &lhs - &rhs,
+            Op::Mul => &lhs * &rhs,
+            Op::Div => &lhs / &rhs,
+        };
+        Ok(match result.to_i64() {
+            Some(result) => Value::Small(result),
+            None => Value::Big(result),
+        })
+    }
+
+    fn get(&self, key: Key) -> &Value {
+        &self.variables[&key]
     }
+}
+
+impl Value {
+    fn to_big(&self) -> BigInt {
+        match self {
+            Self::Small(n) => BigInt::from(*n),
+            Self::Big(n) => n.clone(),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Small(n) => write!(f, "{n}"),
+            Self::Big(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+impl Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { key, op, lhs, rhs } = self;
+        write!(f, "{key:?}: {lhs} {op} {rhs} overflows")
+    }
+}
+
+impl Evaluation {
+    /// Where the computation would have overflowed, one operation per line.
+    pub fn report(&self) -> String {
+        self.overflows.iter().map(|o| format!("{o}\n")).collect()
+    }
+}
 
-    fn get(&self, key: Key) -> i64 {
-        self.variables[&key]
+/// Computes the number `root` yells.
+pub fn evaluate(map: &FxHashMap<Key, Entry>, root: Key) -> Result<Evaluation, SolveError> {
+    let mut memory = Memory::default();
+    for key in evaluation_order(map, root)? {
+        memory.run(map[&key])?;
     }
+    Ok(Evaluation {
+        value: memory.get(root).clone(),
+        overflows: memory.overflows,
+    })
+}
+
+impl Key {
+    pub const ROOT: Self = Self(u32::from_be_bytes(*b"root"));
+    pub const HUMAN: Self = Self(u32::from_be_bytes(*b"humn"));
 }
 
 impl FromStr for Key {
-    type Err = Infallible;
+    type Err = ProgramError;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.as_bytes();
-        assert!(s.len() == 4);
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let s = name.as_bytes();
+        if s.len() != 4 || !s.iter().all(u8::is_ascii_alphanumeric) {
+            return Err(ProgramError::BadName(name.to_string()));
+        }
         Ok(Key((s[0] as u32) << 24
             | (s[1] as u32) << 16
             | (s[2] as u32) << 8
@@ -69,154 +270,699 @@ impl Debug for Key {
 }
 
 impl Instruction {
-    fn run_dependencies(&self, map: &FxHashMap<Key, Entry>, mem: &mut Memory) -> bool {
-        match self {
-            Self::Add(x, y) | Self::Sub(x, y) | Self::Mul(x, y) | Self::Div(x, y) => {
-                let r1 = map[x].instruction.run_dependencies(map, mem);
-                let r2 = map[y].instruction.run_dependencies(map, mem);
-                if r1 {
-                    mem.run(map[x]);
+    fn operands(&self) -> Option<(Op, Key, Key)> {
+        match *self {
+            Self::Add(x, y) => Some((Op::Add, x, y)),
+            Self::Sub(x, y) => Some((Op::Sub, x, y)),
+            Self::Mul(x, y) => Some((Op::Mul, x, y)),
+            Self::Div(x, y) => Some((Op::Div, x, y)),
+            Self::Input | Self::Immediate(_) => None,
+        }
+    }
+}
+
+impl Expr {
+    /// Builds the expression of the monkey `key`, where the `human` monkey is
+    /// the unknown.
+    pub fn build(map: &FxHashMap<Key, Entry>, key: Key, human: Key) -> Result<Self, ProgramError> {
+        let mut expr = Self {
+            nodes: vec![],
+            human: vec![],
+        };
+        let mut index = FxHashMap::default();
+        for key in evaluation_order(map, key)? {
+            let instruction = map[&key].instruction;
+            let node = match instruction.operands() {
+                _ if key == human || instruction == Instruction::Input => Node::Human(key),
+                Some((op, x, y)) => Node::Binary {
+                    key,
+                    op,
+                    lhs: index[&x],
+                    rhs: index[&y],
+                },
+                None => match instruction {
+                    Instruction::Immediate(imm) => Node::Constant(imm),
+                    _ => unreachable!(),
+                },
+            };
+            index.insert(key, expr.push(node));
+        }
+        Ok(expr)
+    }
+
+    fn push(&mut self, node: Node) -> usize {
+        let human = match node {
+            Node::Human(_) => true,
+            Node::Constant(_) => false,
+            Node::Binary { lhs, rhs, .. } => self.human[lhs] || self.human[rhs],
+        };
+        self.nodes.push(node);
+        self.human.push(human);
+        self.nodes.len() - 1
+    }
+
+    fn root(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Replaces operations on constants with their result. Divisions that
+    /// aren't exact and operations that overflow are kept as they are.
+    pub fn fold(self) -> Self {
+        let mut folded = Self {
+            nodes: vec![],
+            human: vec![],
+        };
+        let mut operands: Vec<Folded> = Vec::with_capacity(self.nodes.len());
+        for &node in &self.nodes {
+            let result = match node {
+                Node::Constant(c) => Folded::Constant(c),
+                Node::Human(_) => Folded::Node(folded.push(node)),
+                Node::Binary { key, op, lhs, rhs } => {
+                    let (lhs, rhs) = (operands[lhs], operands[rhs]);
+                    let constant = match (lhs, rhs) {
+                        (Folded::Constant(x), Folded::Constant(y)) => match op {
+                            Op::Add => x.checked_add(y),
+                            Op::Sub => x.checked_sub(y),
+                            Op::Mul => x.checked_mul(y),
+                            Op::Div => x
+                                .checked_rem(y)
+                                .filter(|&r| r == 0)
+                                .and_then(|_| x.checked_div(y)),
+                        },
+                        _ => None,
+                    };
+                    match constant {
+                        Some(c) => Folded::Constant(c),
+                        None => {
+                            let lhs = folded.store(lhs);
+                            let rhs = folded.store(rhs);
+                            Folded::Node(folded.push(Node::Binary { key, op, lhs, rhs }))
+                        }
+                    }
+                }
+            };
+            operands.push(result);
+        }
+        if let Some(&Folded::Constant(c)) = operands.last() {
+            folded.push(Node::Constant(c));
+        }
+        folded
+    }
+
+    fn store(&mut self, operand: Folded) -> usize {
+        match operand {
+            Folded::Constant(c) => self.push(Node::Constant(c)),
+            Folded::Node(i) => i,
+        }
+    }
+
+    pub fn contains_human(&self) -> bool {
+        self.human[self.root()]
+    }
+
+    fn precedence(&self, i: usize) -> u8 {
+        match self.nodes[i] {
+            Node::Binary { op, .. } => op.precedence(),
+            _ => u8::MAX,
+        }
+    }
+
+    /// Writes the nodes of the expression as `id`, `id + 1`, ... in preorder,
+    /// returning the next free id.
+    fn write_dot(&self, out: &mut String, id: usize) -> usize {
+        let mut next = id;
+        let mut stack = vec![(self.root(), None)];
+        while let Some((i, parent)) = stack.pop() {
+            let id = next;
+            next += 1;
+            if let Some(parent) = parent {
+                writeln!(out, "  n{parent} -> n{id};").unwrap();
+            }
+            let color = if self.human[i] { ", color=red" } else { "" };
+            match self.nodes[i] {
+                Node::Human(key) => {
+                    writeln!(
+                        out,
+                        "  n{id} [label=\"{key:?}\", shape=doublecircle{color}];"
+                    )
+                    .unwrap();
                 }
-                if r2 {
-                    mem.run(map[y]);
+                Node::Constant(c) => {
+                    writeln!(out, "  n{id} [label=\"{c}\", shape=box];").unwrap();
+                }
+                Node::Binary { key, op, lhs, rhs } => {
+                    writeln!(out, "  n{id} [label=\"{key:?}\\n{op}\"{color}];").unwrap();
+                    stack.push((rhs, Some(id)));
+                    stack.push((lhs, Some(id)));
                 }
-                r1 && r2
             }
-            Self::Input => false,
-            _ => true,
         }
+        next
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut stack = vec![Piece::Node(self.root())];
+        while let Some(piece) = stack.pop() {
+            let i = match piece {
+                Piece::Node(i) => i,
+                Piece::Op(op) => {
+                    write!(f, " {op} ")?;
+                    continue;
+                }
+                Piece::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+            };
+            let (op, lhs, rhs) = match self.nodes[i] {
+                Node::Human(key) => {
+                    write!(f, "{key:?}")?;
+                    continue;
+                }
+                Node::Constant(c) => {
+                    write!(f, "{c}")?;
+                    continue;
+                }
+                Node::Binary { op, lhs, rhs, .. } => (op, lhs, rhs),
+            };
+            // Only + and * can be regrouped from the right, and / truncates
+            // so `a * (b / c)` must keep its parentheses
+            let rhs_grouped = match self.nodes[rhs] {
+                Node::Binary { op: rhs_op, .. } => rhs_op == op && matches!(op, Op::Add | Op::Mul),
+                _ => false,
+            };
+            let lhs_parens = self.precedence(lhs) < op.precedence();
+            let rhs_parens = self.precedence(rhs) < op.precedence()
+                || (self.precedence(rhs) == op.precedence() && !rhs_grouped);
+            // Pushed in reverse, to be printed left to right
+            push_operand(&mut stack, rhs, rhs_parens);
+            stack.push(Piece::Op(op));
+            push_operand(&mut stack, lhs, lhs_parens);
+        }
+        Ok(())
+    }
+}
+
+fn push_operand(stack: &mut Vec<Piece>, i: usize, parens: bool) {
+    if parens {
+        stack.push(Piece::Text(")"));
+    }
+    stack.push(Piece::Node(i));
+    if parens {
+        stack.push(Piece::Text("("));
+    }
+}
+
+impl Op {
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        };
+        write!(f, "{symbol}")
     }
 }
 
-impl Entry {
-    fn run(&self, map: &FxHashMap<Key, Entry>, mem: &mut Memory) -> Option<i64> {
-        self.instruction.run_dependencies(map, mem).then(|| {
-            mem.run(*self);
-            mem.get(self.key)
+/// The test `root` does on its two operands, with constants folded.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Equation {
+    pub lhs: Expr,
+    pub rhs: Expr,
+}
+
+impl Equation {
+    pub fn new(map: &FxHashMap<Key, Entry>, root: Key, human: Key) -> Result<Self, ProgramError> {
+        let entry = map.get(&root).ok_or(ProgramError::Missing(root))?;
+        let Some((_, x, y)) = entry.instruction.operands() else {
+            return Err(ProgramError::NotOperation(root));
+        };
+        Ok(Self {
+            lhs: Expr::build(map, x, human)?.fold(),
+            rhs: Expr::build(map, y, human)?.fold(),
         })
     }
 
-    /// Calculates the immediate value of a branch, if possible.
-    /// If a human node is reached, returns None. Otherwise, it returns the new target value
-    /// after taking the branch into account.
-    fn run_until_human(
-        &self,
-        map: &FxHashMap<Key, Entry>,
-        target_value: i64,
-        memory: &mut Memory,
-    ) -> Result<i64, i64> {
-        match &self.instruction {
-            Instruction::Input => Err(target_value),
-            Instruction::Immediate(_) => Ok(target_value),
-            Instruction::Add(x, y) => match (map[x].run(map, memory), map[y].run(map, memory)) {
-                (Some(vx), None) => map[y].run_until_human(map, target_value - vx, memory),
-                (None, Some(vy)) => map[x].run_until_human(map, target_value - vy, memory),
-                _ => unreachable!(),
-            },
-            Instruction::Sub(x, y) => match (map[x].run(map, memory), map[y].run(map, memory)) {
-                (Some(vx), None) => map[y].run_until_human(map, vx - target_value, memory),
-                (None, Some(vy)) => map[x].run_until_human(map, target_value + vy, memory),
-                _ => unreachable!(),
-            },
-            Instruction::Mul(x, y) => match (map[x].run(map, memory), map[y].run(map, memory)) {
-                (Some(vx), None) => map[y].run_until_human(map, target_value / vx, memory),
-                (None, Some(vy)) => map[x].run_until_human(map, target_value / vy, memory),
-                _ => unreachable!(),
-            },
-            Instruction::Div(x, y) => match (map[x].run(map, memory), map[y].run(map, memory)) {
-                (Some(vx), None) => map[y].run_until_human(map, vx / target_value, memory),
-                (None, Some(vy)) => map[x].run_until_human(map, target_value * vy, memory),
-                _ => unreachable!(),
-            },
+    /// Graphviz DOT rendering of the tree, with the path to the human in red.
+    pub fn to_dot(&self) -> String {
+        let mut out =
+            String::from("digraph monkeys {\n  n0 [label=\"root\\n=\", shape=diamond];\n");
+        let rhs_id = self.lhs.write_dot(&mut out, 1);
+        self.rhs.write_dot(&mut out, rhs_id);
+        writeln!(out, "  n0 -> n1;\n  n0 -> n{rhs_id};").unwrap();
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl Display for Equation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.lhs, self.rhs)
+    }
+}
+
+impl Linear {
+    const HUMAN: Self = Self {
+        a: Rational::ONE,
+        b: Rational::ZERO,
+    };
+
+    fn constant(c: i64) -> Self {
+        Self {
+            a: Rational::ZERO,
+            b: Rational::from(c),
         }
     }
 
-    fn dependencies(&self) -> (Key, Key) {
-        match self.instruction {
-            Instruction::Add(x, y)
-            | Instruction::Sub(x, y)
-            | Instruction::Mul(x, y)
-            | Instruction::Div(x, y) => (x, y),
-            _ => panic!(),
+    fn apply(key: Key, op: Op, lhs: Self, rhs: Self) -> Result<Self, SolveError> {
+        let overflow = || SolveError::Overflow(key);
+        match op {
+            Op::Add | Op::Sub => {
+                let combine = |x: Rational, y: Rational| match op {
+                    Op::Add => x.checked_add(y),
+                    _ => x.checked_sub(y),
+                };
+                Ok(Linear {
+                    a: combine(lhs.a, rhs.a).ok_or_else(overflow)?,
+                    b: combine(lhs.b, rhs.b).ok_or_else(overflow)?,
+                })
+            }
+            Op::Mul => {
+                // One of the factors must be a constant
+                let (factor, other) = match (lhs.a.is_zero(), rhs.a.is_zero()) {
+                    (_, true) => (rhs.b, lhs),
+                    (true, false) => (lhs.b, rhs),
+                    (false, false) => return Err(SolveError::NonLinear(key)),
+                };
+                Ok(Linear {
+                    a: other.a.checked_mul(factor).ok_or_else(overflow)?,
+                    b: other.b.checked_mul(factor).ok_or_else(overflow)?,
+                })
+            }
+            Op::Div => {
+                if !rhs.a.is_zero() {
+                    return Err(SolveError::NonLinear(key));
+                }
+                if rhs.b.is_zero() {
+                    return Err(SolveError::DivisionByZero(key));
+                }
+                Ok(Linear {
+                    a: lhs.a.checked_div(rhs.b).ok_or_else(overflow)?,
+                    b: lhs.b.checked_div(rhs.b).ok_or_else(overflow)?,
+                })
+            }
         }
     }
 }
 
-#[aoc_generator(day21)]
-fn parse(input: &str) -> Vec<Entry> {
-    input
-        .lines()
-        .map(|line| {
-            let words = line.split_whitespace().collect::<Vec<_>>();
-            let key = words[0][..words[0].len() - 1].parse().unwrap();
-            let inst = if words.len() == 2 {
-                Instruction::Immediate(words[1].parse().unwrap())
-            } else {
-                let x = words[1].parse().unwrap();
-                let y = words[3].parse().unwrap();
-                match words[2] {
-                    "+" => Instruction::Add(x, y),
-                    "-" => Instruction::Sub(x, y),
-                    "*" => Instruction::Mul(x, y),
-                    "/" => Instruction::Div(x, y),
-                    op => panic!("invalid op {op}"),
+/// Finds the value of `human` for which both operands of `root` are equal.
+pub fn solve(map: &FxHashMap<Key, Entry>, root: Key, human: Key) -> Result<Rational, SolveError> {
+    let entry = map.get(&root).ok_or(ProgramError::Missing(root))?;
+    let Some((_, x, y)) = entry.instruction.operands() else {
+        return Err(ProgramError::NotOperation(root).into());
+    };
+    let order = evaluation_order(map, root)?;
+    let mut values = FxHashMap::default();
+    for &key in &order[..order.len() - 1] {
+        let instruction = map[&key].instruction;
+        let value = match instruction.operands() {
+            _ if key == human => Linear::HUMAN,
+            Some((op, x, y)) => Linear::apply(key, op, values[&x], values[&y])?,
+            None => match instruction {
+                Instruction::Immediate(imm) => Linear::constant(imm),
+                _ => Linear::HUMAN,
+            },
+        };
+        values.insert(key, value);
+    }
+    let (lhs, rhs) = (values[&x], values[&y]);
+    let overflow = || SolveError::Overflow(root);
+    // (lhs.a - rhs.a) * humn = rhs.b - lhs.b
+    let a = lhs.a.checked_sub(rhs.a).ok_or_else(overflow)?;
+    let b = rhs.b.checked_sub(lhs.b).ok_or_else(overflow)?;
+    match (a.is_zero(), b.is_zero()) {
+        (true, true) => Err(SolveError::AnySolution),
+        (true, false) => Err(SolveError::NoSolution),
+        _ => b.checked_div(a).ok_or_else(overflow),
+    }
+}
+
+/// Monkeys that `root` depends on, each after its operands and ending with
+/// `root`. Walks the monkeys with an explicit stack, so long chains can't
+/// overflow the call stack.
+pub fn evaluation_order(map: &FxHashMap<Key, Entry>, root: Key) -> Result<Vec<Key>, ProgramError> {
+    let mut done = FxHashSet::default();
+    let mut order = vec![];
+    visit(map, root, &mut done, &mut order, false)?;
+    Ok(order)
+}
+
+/// Adds the monkeys `start` depends on to `order`. Undefined operands are
+/// errors, unless `skip_undefined` is set, in which case they are left out
+/// and the walk carries on with the other operands.
+fn visit(
+    map: &FxHashMap<Key, Entry>,
+    start: Key,
+    done: &mut FxHashSet<Key>,
+    order: &mut Vec<Key>,
+    skip_undefined: bool,
+) -> Result<(), ProgramError> {
+    if !done.insert(start) {
+        return Ok(());
+    }
+    // Monkeys being visited, with the number of operands already visited
+    let mut path = vec![(start, 0)];
+    let mut on_path = FxHashSet::from_iter([start]);
+    while let Some((key, visited)) = path.last_mut() {
+        let key = *key;
+        let Some(entry) = map.get(&key) else {
+            return Err(ProgramError::Missing(key));
+        };
+        let operands = match entry.instruction.operands() {
+            Some((_, x, y)) => vec![x, y],
+            None => vec![],
+        };
+        if let Some(&next) = operands.get(*visited) {
+            *visited += 1;
+            if on_path.contains(&next) {
+                let cycle_start = path.iter().position(|&(k, _)| k == next).unwrap();
+                return Err(ProgramError::Cycle(
+                    path[cycle_start..].iter().map(|&(k, _)| k).collect(),
+                ));
+            }
+            if !map.contains_key(&next) {
+                if skip_undefined {
+                    continue;
                 }
-            };
-            Entry {
-                key,
-                instruction: inst,
+                return Err(ProgramError::Undefined {
+                    key: next,
+                    used_by: key,
+                });
+            }
+            if done.insert(next) {
+                on_path.insert(next);
+                path.push((next, 0));
+            }
+        } else {
+            on_path.remove(&key);
+            order.push(key);
+            path.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every monkey is defined once and doesn't depend on itself, and
+/// that `root` compares two monkeys and `humn` exists, reporting every problem
+/// found.
+pub fn validate(entries: &[Entry]) -> Result<FxHashMap<Key, Entry>, Vec<ProgramError>> {
+    let mut errors = vec![];
+    let mut map = FxHashMap::default();
+    for &entry in entries {
+        // Later definitions are reported and ignored
+        match map.entry(entry.key) {
+            hash_map::Entry::Occupied(_) => errors.push(ProgramError::Duplicate(entry.key)),
+            hash_map::Entry::Vacant(slot) => {
+                slot.insert(entry);
             }
+        }
+    }
+    for entry in entries {
+        let operands = entry.instruction.operands();
+        for key in operands.into_iter().flat_map(|(_, x, y)| [x, y]) {
+            if !map.contains_key(&key) {
+                errors.push(ProgramError::Undefined {
+                    key,
+                    used_by: entry.key,
+                });
+            }
+        }
+    }
+    for key in [Key::ROOT, Key::HUMAN] {
+        if !map.contains_key(&key) {
+            errors.push(ProgramError::Missing(key));
+        }
+    }
+    if map
+        .get(&Key::ROOT)
+        .is_some_and(|root| root.instruction.operands().is_none())
+    {
+        errors.push(ProgramError::NotOperation(Key::ROOT));
+    }
+    let mut done = FxHashSet::default();
+    for entry in entries {
+        let mut order = vec![];
+        // Undefined monkeys were already reported above, so walk past them to
+        // find any cycle behind them
+        if let Err(e) = visit(&map, entry.key, &mut done, &mut order, true) {
+            errors.push(e);
+        }
+    }
+    if errors.is_empty() {
+        Ok(map)
+    } else {
+        Err(errors)
+    }
+}
+
+impl Rational {
+    pub const ZERO: Self = Self { num: 0, den: 1 };
+    pub const ONE: Self = Self { num: 1, den: 1 };
+
+    /// `num / den`, or `None` if `den` is 0.
+    pub fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den).max(1);
+        let sign = den.signum();
+        Some(Self {
+            num: sign * num / g,
+            den: sign * den / g,
+        })
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.num == 0
+    }
+
+    pub fn to_integer(self) -> Option<i128> {
+        (self.den == 1).then_some(self.num)
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let g = gcd(self.den, other.den);
+        let num = (self.num.checked_mul(other.den / g)?)
+            .checked_add(other.num.checked_mul(self.den / g)?)?;
+        Self::new(num, (self.den / g).checked_mul(other.den)?)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(Self {
+            num: other.num.checked_neg()?,
+            den: other.den,
         })
-        .collect()
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        // Cross-reduce first to keep the numbers small
+        let g1 = gcd(self.num, other.den).max(1);
+        let g2 = gcd(other.num, self.den).max(1);
+        Self::new(
+            (self.num / g1).checked_mul(other.num / g2)?,
+            (self.den / g2).checked_mul(other.den / g1)?,
+        )
+    }
+
+    /// Returns `None` on overflow or division by zero.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let inverse = Self::new(other.den, other.num)?;
+        self.checked_mul(inverse)
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(n: i64) -> Self {
+        Self {
+            num: n as i128,
+            den: 1,
+        }
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Display for SolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Program(e) => write!(f, "{e}"),
+            Self::NonLinear(key) => write!(f, "{key:?} is not linear in the human's number"),
+            Self::DivisionByZero(key) => write!(f, "{key:?} divides by zero"),
+            Self::Overflow(key) => write!(f, "{key:?} overflows"),
+            Self::AnySolution => write!(f, "any number passes the equality test"),
+            Self::NoSolution => write!(f, "no number passes the equality test"),
+            Self::NotInteger(r) => write!(f, "the only number that passes is {r}"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Program(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ProgramError> for SolveError {
+    fn from(e: ProgramError) -> Self {
+        Self::Program(e)
+    }
+}
+
+impl Display for ProgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadName(name) => write!(f, "invalid monkey name {name:?}"),
+            Self::BadLine(line) => write!(f, "invalid line {line:?}"),
+            Self::Undefined { key, used_by } => {
+                write!(f, "{key:?} is used by {used_by:?} but never defined")
+            }
+            Self::Duplicate(key) => write!(f, "{key:?} is defined more than once"),
+            Self::Missing(key) => write!(f, "{key:?} is never defined"),
+            Self::NotOperation(key) => write!(f, "{key:?} must be an operation"),
+            Self::Cycle(keys) => {
+                write!(f, "cycle: ")?;
+                for key in keys {
+                    write!(f, "{key:?} -> ")?;
+                }
+                write!(f, "{:?}", keys[0])
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProgramError {}
+
+impl FromStr for Entry {
+    type Err = ProgramError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let bad_line = || ProgramError::BadLine(line.to_string());
+        let (key, rest) = line.split_once(':').ok_or_else(bad_line)?;
+        let key = key.parse()?;
+        let words = rest.split_whitespace().collect::<Vec<_>>();
+        let instruction = match words[..] {
+            [imm] => Instruction::Immediate(imm.parse().map_err(|_| bad_line())?),
+            [x, op, y] => {
+                let op = match op {
+                    "+" => Instruction::Add,
+                    "-" => Instruction::Sub,
+                    "*" => Instruction::Mul,
+                    "/" => Instruction::Div,
+                    _ => return Err(bad_line()),
+                };
+                op(x.parse()?, y.parse()?)
+            }
+            _ => return Err(bad_line()),
+        };
+        Ok(Entry { key, instruction })
+    }
+}
+
+/// Parses and validates the monkeys, reporting every problem found.
+pub fn check(input: &str) -> Result<Vec<Entry>, Vec<ProgramError>> {
+    let (entries, mut errors): (Vec<_>, Vec<_>) =
+        input.lines().map(str::parse).partition(Result::is_ok);
+    let entries = entries.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+    let mut errors = errors.drain(..).map(Result::unwrap_err).collect::<Vec<_>>();
+    if let Err(e) = validate(&entries) {
+        errors.extend(e);
+    }
+    if errors.is_empty() {
+        Ok(entries)
+    } else {
+        Err(errors)
+    }
+}
+
+#[aoc_generator(day21)]
+pub fn parse(input: &str) -> Vec<Entry> {
+    check(input).unwrap_or_else(|errors| {
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        panic!("invalid monkeys:\n{}", errors.join("\n"))
+    })
 }
 
 #[aoc(day21, part1)]
 pub fn part1(input: &[Entry]) -> i64 {
-    let mut memory = Memory::default();
-    let root: Key = "root".parse().unwrap();
+    let evaluation = part1_evaluation(input).unwrap_or_else(|e| panic!("{e}"));
+    match &evaluation.value {
+        Value::Small(n) => *n,
+        Value::Big(n) => panic!(
+            "{n} doesn't fit in an i64, overflows:\n{}",
+            evaluation.report()
+        ),
+    }
+}
+
+pub fn part1_big(input: &[Entry]) -> Result<Value, SolveError> {
+    part1_evaluation(input).map(|evaluation| evaluation.value)
+}
+
+fn part1_evaluation(input: &[Entry]) -> Result<Evaluation, SolveError> {
     let entry_map = input
         .iter()
         .cloned()
         .map(|e| (e.key, e))
         .collect::<FxHashMap<_, _>>();
-    entry_map[&root]
-        .instruction
-        .run_dependencies(&entry_map, &mut memory);
-    memory.run(entry_map[&root]);
-    memory.get(root)
+    evaluate(&entry_map, Key::ROOT)
 }
 
 #[aoc(day21, part2)]
 pub fn part2(input: &[Entry]) -> i64 {
-    let mut memory = Memory::default();
-    let root: Key = "root".parse().unwrap();
-    let mut entry_map = input
+    let entry_map = input
         .iter()
         .cloned()
         .map(|e| (e.key, e))
         .collect::<FxHashMap<_, _>>();
-    entry_map.insert(
-        "humn".parse().unwrap(),
-        Entry {
-            key: "humn".parse().unwrap(),
-            instruction: Instruction::Input,
-        },
-    );
-    let deps = entry_map[&root].dependencies();
-    let deps: (Vec<Key>, Vec<Key>) = [deps.0, deps.1]
-        .into_iter()
-        .partition(|k| entry_map[k].run(&entry_map, &mut memory).is_some());
-    let immediate_branch = deps.0[0];
-    let human_branch = deps.1[0];
-
-    // From the root, we have two branches, one of which contains the "humn" node.
-    // Our target value will start with the value of the "immediate" branch, i.e. the one
-    // without the "humn" node.
-    memory.run(entry_map[&immediate_branch]);
-    let target_value = memory.get(immediate_branch);
-    entry_map[&human_branch]
-        .run_until_human(&entry_map, target_value, &mut memory)
-        .unwrap_err()
+    let humn = solve(&entry_map, Key::ROOT, Key::HUMAN).and_then(|r| {
+        r.to_integer()
+            .and_then(|n| i64::try_from(n).ok())
+            .ok_or(SolveError::NotInteger(r))
+    });
+    humn.unwrap_or_else(|e| panic!("{e}"))
 }
 
 #[cfg(test)]
@@ -262,4 +1008,223 @@ root: pppw + sjmn
 hmdt: 32"#;
         assert_eq!(part2(&parse(input)), 301);
     }
+
+    fn solve_str(input: &str) -> Result<Rational, SolveError> {
+        let map = parse(input)
+            .into_iter()
+            .map(|e| (e.key, e))
+            .collect::<FxHashMap<_, _>>();
+        solve(&map, "root".parse().unwrap(), "humn".parse().unwrap())
+    }
+
+    #[test]
+    fn solve_repeated_human() {
+        // (humn + humn * 3) / 8 = humn - 1
+        let input = r#"root: left + rght
+left: sumh / eigt
+sumh: humn + trpl
+trpl: humn * thre
+thre: 3
+eigt: 8
+rght: humn - one1
+one1: 1
+humn: 0"#;
+        assert_eq!(solve_str(input), Ok(Rational::from(2)));
+
+        // humn / 3 = 1, which truncating division would also satisfy with 4
+        let input = "root: divd * one1\ndivd: humn / thre\nthre: 3\none1: 1\nhumn: 0";
+        assert_eq!(solve_str(input), Ok(Rational::from(3)));
+
+        let input = "root: half + zero\nhalf: humn * two2\ntwo2: 2\nzero: 1\nhumn: 0";
+        assert_eq!(
+            solve_str(input),
+            Rational::new(1, 2).ok_or(SolveError::NoSolution)
+        );
+    }
+
+    #[test]
+    fn solve_errors() {
+        let square = "root: sqre + four\nsqre: humn * humn\nfour: 4\nhumn: 0";
+        assert_eq!(
+            solve_str(square),
+            Err(SolveError::NonLinear("sqre".parse().unwrap()))
+        );
+        let inverse = "root: invr + four\ninvr: four / humn\nfour: 4\nhumn: 0";
+        assert_eq!(
+            solve_str(inverse),
+            Err(SolveError::NonLinear("invr".parse().unwrap()))
+        );
+        let zero = "root: divd + four\ndivd: humn / zero\nzero: 0\nfour: 4\nhumn: 0";
+        assert_eq!(
+            solve_str(zero),
+            Err(SolveError::DivisionByZero("divd".parse().unwrap()))
+        );
+        let any = "root: humn + same\nsame: humn * one1\none1: 1\nhumn: 0";
+        assert_eq!(solve_str(any), Err(SolveError::AnySolution));
+        let none = "root: plus + humn\nplus: humn + one1\none1: 1\nhumn: 0";
+        assert_eq!(solve_str(none), Err(SolveError::NoSolution));
+    }
+
+    #[test]
+    fn validation() {
+        let input = "root: abcd + efgh\nabcd: efgh * 2bad\nefgh: abcd - ijkl\nroot: 3\n\
+                     humn: 5\nlong: 1\nnope: 3 x 4\ntoolong: 2";
+        let key = |name: &str| name.parse::<Key>().unwrap();
+        assert_eq!(
+            check(input),
+            Err(vec![
+                ProgramError::BadLine("nope: 3 x 4".to_string()),
+                ProgramError::BadName("toolong".to_string()),
+                ProgramError::Duplicate(key("root")),
+                ProgramError::Undefined {
+                    key: key("2bad"),
+                    used_by: key("abcd")
+                },
+                ProgramError::Undefined {
+                    key: key("ijkl"),
+                    used_by: key("efgh")
+                },
+                ProgramError::Cycle(vec![key("abcd"), key("efgh")]),
+            ])
+        );
+        let input = "root: aaaa + humn\nhumn: 1\naaaa: xxxx + bbbb\nbbbb: aaaa * cccc\ncccc: 2";
+        assert_eq!(
+            check(input),
+            Err(vec![
+                ProgramError::Undefined {
+                    key: key("xxxx"),
+                    used_by: key("aaaa")
+                },
+                ProgramError::Cycle(vec![key("aaaa"), key("bbbb")]),
+            ])
+        );
+        assert_eq!(
+            check("roo7: 1\nhumm: 2"),
+            Err(vec![
+                ProgramError::Missing(Key::ROOT),
+                ProgramError::Missing(Key::HUMAN)
+            ])
+        );
+        assert_eq!(
+            check("root: 1\nhumn: 2"),
+            Err(vec![ProgramError::NotOperation(Key::ROOT)])
+        );
+        let map = FxHashMap::default();
+        assert_eq!(
+            solve(&map, Key::ROOT, Key::HUMAN),
+            Err(SolveError::Program(ProgramError::Missing(Key::ROOT)))
+        );
+        assert_eq!(
+            evaluation_order(&map, Key::ROOT).err(),
+            Some(ProgramError::Missing(Key::ROOT))
+        );
+        let input = parse("root: humn / zero\nzero: 0\nhumn: 5");
+        assert_eq!(
+            part1_big(&input),
+            Err(SolveError::DivisionByZero(Key::ROOT))
+        );
+        assert_eq!("2bad: 1".parse::<Entry>().map(|e| e.key), Ok(key("2bad")));
+        assert!("x: 1".parse::<Entry>().is_err());
+    }
+
+    #[test]
+    fn deep_chain() {
+        let depth = 40_000;
+        let digit = |d: usize| char::from_digit(d as u32, 36).unwrap();
+        let name = |i: usize| {
+            format!(
+                "m{}{}{}",
+                digit(i / 1296),
+                digit(i / 36 % 36),
+                digit(i % 36)
+            )
+        };
+        let mut input = format!("root: m000 + cnst\ncnst: {}\nhumn: 5\n", 1 + 7 * depth);
+        for i in 0..depth {
+            input += &format!("{}: {} + humn\n", name(i), name(i + 1));
+        }
+        input += &format!("{}: 1", name(depth));
+        let input = parse(&input);
+        assert_eq!(
+            part1(&input),
+            (1 + 5 * depth as i64) + (1 + 7 * depth as i64)
+        );
+        assert_eq!(part2(&input), 7);
+
+        let map = input.into_iter().map(|e| (e.key, e)).collect();
+        let equation = Equation::new(&map, Key::ROOT, Key::HUMAN).unwrap();
+        let expected = format!("1{} = {}", " + humn".repeat(depth), 1 + 7 * depth);
+        assert_eq!(equation.to_string(), expected);
+        assert_eq!(equation.to_dot().matches("humn").count(), depth);
+    }
+
+    #[test]
+    fn equation_example() {
+        let input = r#"dbpl: 5
+cczh: sllz + lgvd
+zczc: 2
+dvpt: 3
+lfqf: 4
+humn: 5
+ljgn: 2
+sjmn: drzm * dbpl
+sllz: 4
+pppw: cczh / lfqf
+lgvd: ljgn * ptdq
+drzm: hmdt - zczc
+ptdq: humn - dvpt
+root: pppw + sjmn
+hmdt: 32"#;
+        let map = parse(input)
+            .into_iter()
+            .map(|e| (e.key, e))
+            .collect::<FxHashMap<_, _>>();
+        let equation = Equation::new(&map, Key::ROOT, Key::HUMAN).unwrap();
+        assert_eq!(equation.to_string(), "(4 + 2 * (humn - 3)) / 4 = 150");
+
+        let dot = equation.to_dot();
+        assert!(dot.starts_with("digraph monkeys {\n  n0 [label=\"root\\n=\", shape=diamond];\n"));
+        assert!(dot.contains("  n1 [label=\"pppw\\n/\", color=red];\n"));
+        assert!(dot.contains("  n7 [label=\"humn\", shape=doublecircle, color=red];\n"));
+        assert!(dot.contains("  n10 [label=\"150\", shape=box];\n"));
+        assert!(dot.ends_with("  n0 -> n1;\n  n0 -> n10;\n}\n"));
+
+        let expr = |input: &str| {
+            let map = parse(input)
+                .into_iter()
+                .map(|e| (e.key, e))
+                .collect::<FxHashMap<_, _>>();
+            Expr::build(&map, Key::ROOT, Key::HUMAN).unwrap().fold()
+        };
+        // 7 / 2 truncates, so it isn't folded
+        let input = "root: humn * divd\ndivd: sevn / twoo\nsevn: 7\ntwoo: 2\nhumn: 0";
+        assert_eq!(expr(input).to_string(), "humn * (7 / 2)");
+        let input = "root: subs - humn\nsubs: humn - humn\nhumn: 0";
+        assert_eq!(expr(input).to_string(), "humn - humn - humn");
+        let input = "root: humn - subs\nsubs: humn - humn\nhumn: 0";
+        assert_eq!(expr(input).to_string(), "humn - (humn - humn)");
+    }
+
+    #[test]
+    fn overflow() {
+        // 2^62 * 4 doesn't fit, but dividing it back does
+        let input = "root: bigg / eigt\nbigg: maxx * four\nmaxx: 4611686018427387904\n\
+                     four: 4\neigt: 8\nhumn: 0";
+        let input = parse(input);
+        assert_eq!(part1(&input), 1 << 61);
+        let evaluation = part1_evaluation(&input).unwrap();
+        assert_eq!(
+            evaluation.report(),
+            "bigg: 4611686018427387904 * 4 overflows\n"
+        );
+
+        let input = "root: bigg + bigg\nbigg: maxx * four\nmaxx: 4611686018427387904\n\
+                     four: 4\nhumn: 0";
+        let input = parse(input);
+        assert_eq!(
+            part1_big(&input).unwrap().to_string(),
+            "36893488147419103232"
+        );
+        assert_eq!(part1_evaluation(&input).unwrap().overflows.len(), 1);
+    }
 }
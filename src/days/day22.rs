@@ -1,22 +1,67 @@
-use std::ops::RangeInclusive;
+use std::{fmt::Display, ops::RangeInclusive};
 
-use fxhash::{FxHashMap, FxHashSet};
+use fxhash::FxHashSet;
 use regex::Regex;
 
+use crate::util::image::Image;
+
 #[derive(Debug)]
 pub struct Grid {
     walls: FxHashSet<(isize, isize)>,
     bounds: Vec<(RangeInclusive<isize>, RangeInclusive<isize>)>,
     starting_pos: (isize, isize),
     directions: Vec<Direction>,
-    faces: FxHashMap<char, Rect>,
-    edges: FxHashMap<(char, Facing), (char, Facing)>,
+    // Only needed for part 2, which fails if the map isn't a cube net
+    cube: Result<Cube, CubeError>,
+    map: DenseMap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Void,
+    Open,
+    Wall,
+}
+
+/// Tiles stored row by row, with the first and last tile of every row and
+/// column so that wrapping around is a lookup.
+#[derive(Debug)]
+struct DenseMap {
+    width: isize,
+    height: isize,
+    tiles: Vec<Tile>,
+    rows: Vec<(isize, isize)>,
+    columns: Vec<(isize, isize)>,
+}
+
+type Vec3 = [isize; 3];
+
+/// The map folded into a cube, with the orientation of each face in space.
+#[derive(Debug)]
+struct Cube {
+    size: isize,
+    faces: Vec<Face>,
+}
+
+/// Why the map can't be folded into a cube.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeError {
+    // The number of tiles isn't 6 times a square
+    TileCount(usize),
+    // The tiles cover this many squares of the face size instead of 6
+    Faces(usize),
+    // The 6 faces aren't connected or overlap when folded
+    NotNet,
 }
 
 #[derive(Debug, Clone, Copy)]
-struct Rect {
-    top: (isize, isize),
-    bottom: (isize, isize),
+struct Face {
+    // Top left tile of the face on the map
+    origin: (isize, isize),
+    // Unit vectors pointing out of the face, and towards +x and +y on the map
+    normal: Vec3,
+    right: Vec3,
+    down: Vec3,
 }
 
 #[derive(Debug)]
@@ -26,57 +71,24 @@ enum Direction {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-enum Facing {
+pub enum Facing {
     Up = 3,
     Down = 1,
     Left = 2,
     Right = 0,
 }
 
-use Facing::*;
-
-//  AB
-//  C
-// ED
-// F
-// (old face, old facing) -> (new face, new facing)
-const EDGES: [((char, Facing), (char, Facing)); 14] = [
-    (('A', Up), ('F', Right)),
-    (('A', Left), ('E', Right)),
-    (('B', Up), ('F', Up)),
-    (('B', Down), ('C', Left)),
-    (('B', Right), ('D', Left)),
-    (('C', Left), ('E', Down)),
-    (('C', Right), ('B', Up)),
-    (('D', Right), ('B', Left)),
-    (('D', Down), ('F', Left)),
-    (('E', Up), ('C', Right)),
-    (('E', Left), ('A', Right)),
-    (('F', Down), ('B', Down)),
-    (('F', Right), ('D', Up)),
-    (('F', Left), ('A', Down)),
-];
-
-const RANGES: [(char, Rect); 6] = [
-    ('A', Rect::new(50, 0, 100, 50)),
-    ('B', Rect::new(100, 0, 150, 50)),
-    ('C', Rect::new(50, 50, 100, 100)),
-    ('E', Rect::new(0, 100, 50, 150)),
-    ('D', Rect::new(50, 100, 100, 150)),
-    ('F', Rect::new(0, 150, 50, 200)),
-];
-
-const RANGES_EXAMPLE: [(char, Rect); 6] = [
-    ('A', Rect::new(4, 0, 8, 4)),
-    ('B', Rect::new(8, 0, 12, 4)),
-    ('C', Rect::new(4, 4, 8, 8)),
-    ('E', Rect::new(0, 8, 4, 12)),
-    ('D', Rect::new(4, 8, 8, 12)),
-    ('F', Rect::new(0, 12, 4, 16)),
-];
-
 impl Grid {
     fn get_next_pos(&self, current_pos: (isize, isize), facing: Facing) -> Option<(isize, isize)> {
+        let next = self.map.step(current_pos, facing);
+        (self.map.get(next) != Tile::Wall).then_some(next)
+    }
+
+    fn get_next_pos_ranges(
+        &self,
+        current_pos: (isize, isize),
+        facing: Facing,
+    ) -> Option<(isize, isize)> {
         let pos_mod = facing.get_pos_mod();
         let new_pos = (current_pos.0 + pos_mod.0, current_pos.1 + pos_mod.1);
         let mut wrapped_pos = (isize::MIN, isize::MIN);
@@ -139,84 +151,305 @@ impl Grid {
     ) -> Option<((isize, isize), Facing)> {
         let pos_mod = facing.get_pos_mod();
         let new_pos = (current_pos.0 + pos_mod.0, current_pos.1 + pos_mod.1);
-        let mut wrapped_pos = ((isize::MIN, isize::MIN), facing);
-        let mut found = false;
-        for (y_bounds, x_bounds) in &self.bounds {
-            match (
-                x_bounds.contains(&new_pos.0),
-                y_bounds.contains(&new_pos.1),
-                y_bounds.contains(&current_pos.1),
-            ) {
-                (true, true, _) => {
-                    wrapped_pos = (new_pos, facing);
-                    found = true;
-                    break;
+        let cube = self.cube();
+        let face = cube.face_of(current_pos);
+        // Moving within a face never changes the facing, otherwise we fold
+        // over to another face, even if it's next to this one on the map
+        let wrapped_pos = if cube.face_of(new_pos) == face {
+            (new_pos, facing)
+        } else {
+            cube.wrap(current_pos, facing)
+        };
+        (self.map.get(wrapped_pos.0) != Tile::Wall).then_some(wrapped_pos)
+    }
+
+    fn cube(&self) -> &Cube {
+        self.cube.as_ref().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Whether the map folds into a cube, which the walk on the cube and
+    /// [`Grid::render_faces`] need.
+    pub fn check_cube(&self) -> Result<(), CubeError> {
+        self.cube.as_ref().map(|_| ()).map_err(|&e| e)
+    }
+
+    /// Follows the directions, where `next` gives the position and facing after
+    /// a step, or `None` if there's a wall. `visit` is called with the start and
+    /// after every step or turn. Returns the password.
+    fn walk(
+        &self,
+        next: impl Fn((isize, isize), Facing) -> Option<((isize, isize), Facing)>,
+        mut visit: impl FnMut((isize, isize), Facing),
+    ) -> isize {
+        let mut pos = self.starting_pos;
+        let mut facing = Facing::Right;
+        visit(pos, facing);
+        for direction in &self.directions {
+            match direction {
+                Direction::Turn(r) => {
+                    facing = facing.turn(*r);
+                    visit(pos, facing);
                 }
-                (false, _, true) => {
-                    wrapped_pos = self.wrap_cube(current_pos, facing);
-                    found = true;
-                    break;
+                Direction::Move(n) => {
+                    for _ in 0..*n {
+                        match next(pos, facing) {
+                            Some((new_pos, new_facing)) => {
+                                pos = new_pos;
+                                facing = new_facing;
+                                visit(pos, facing);
+                            }
+                            None => break,
+                        }
+                    }
                 }
-                _ => continue,
             }
         }
-        if !found {
-            wrapped_pos = self.wrap_cube(current_pos, facing);
+
+        1000 * (pos.1 + 1) + 4 * (pos.0 + 1) + facing as isize
+    }
+
+    /// Every tile visited on the flat map (or on the cube), with the facing when
+    /// leaving it.
+    pub fn path(&self, cube: bool) -> Vec<((isize, isize), Facing)> {
+        let mut path = vec![];
+        let visit = |pos, facing| path.push((pos, facing));
+        if cube {
+            self.walk(|pos, facing| self.get_next_cube_pos(pos, facing), visit);
+        } else {
+            self.walk(
+                |pos, facing| Some((self.get_next_pos(pos, facing)?, facing)),
+                visit,
+            );
         }
-        (!self.walls.contains(&wrapped_pos.0)).then_some(wrapped_pos)
+        path
     }
 
-    fn wrap_cube(&self, prev_pos: (isize, isize), prev_facing: Facing) -> ((isize, isize), Facing) {
-        let prev_cube_id = *self
-            .faces
-            .iter()
-            .find(|(_, rect)| rect.is_inside(prev_pos))
-            .unwrap()
-            .0;
-        let prev_cube = self.faces[&prev_cube_id];
-        let (rel_x, rel_y) = (prev_pos.0 - prev_cube.top.0, prev_pos.1 - prev_cube.top.1);
-        // Our new pos will use either X or Y to shift along the edge
-        let (next_cube_id, next_facing) = self.edges[&(prev_cube_id, prev_facing)];
-        let relative_coord = match (prev_facing, next_facing) {
-            (Up, Right) | (Up, Up) => rel_x,
-            (Up, _) => prev_cube.bottom.0 - prev_pos.0,
-            (Down, Left) | (Down, Down) => rel_x,
-            (Down, _) => prev_cube.bottom.0 - prev_pos.0,
-            (Left, Down) | (Left, Left) => rel_y,
-            (Left, _) => prev_cube.bottom.1 - prev_pos.1,
-            (Right, Up) | (Right, Right) => rel_y,
-            (Right, _) => prev_cube.bottom.1 - prev_pos.1,
+    /// The map with the path drawn over it, like in the puzzle. Tiles show the
+    /// last facing they were visited with.
+    pub fn render(&self, path: &[((isize, isize), Facing)]) -> String {
+        let mut lines = (0..self.map.height)
+            .map(|y| {
+                (0..self.map.width)
+                    .map(|x| match self.map.get((x, y)) {
+                        Tile::Void => ' ',
+                        Tile::Open => '.',
+                        Tile::Wall => '#',
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for &((x, y), facing) in path {
+            lines[y as usize][x as usize] = facing.symbol();
+        }
+        lines
+            .into_iter()
+            .map(|line| line.into_iter().collect::<String>().trim_end().to_string() + "\n")
+            .collect()
+    }
+
+    /// The map with each tile coloured by the side of the cube it folds to, so
+    /// the edges between faces are easy to follow. Walls are darker, and the
+    /// path is drawn at full brightness, ending in white.
+    pub fn render_faces(&self, path: &[((isize, isize), Facing)], scale: usize) -> Image {
+        let (width, height) = (self.map.width as usize, self.map.height as usize);
+        let mut image = Image::new(width * scale, height * scale);
+        let cube = self.cube();
+        let color = |pos: (isize, isize), brightness: u16| {
+            let face = &cube.faces[cube.face_of(pos).unwrap()];
+            // Opposite faces get complementary colours
+            let base = face.normal.map(|c| (128 + 127 * c) as u16);
+            base.map(|c| (c * brightness / 4) as u8)
         };
-        let next_cube = self.faces[&next_cube_id];
-        let next_pos = match next_facing {
-            Down => {
-                // We're looking down, so we must be at the top edge
-                (next_cube.top.0 + relative_coord, next_cube.top.1)
+        for y in 0..height {
+            for x in 0..width {
+                let pos = (x as isize, y as isize);
+                let brightness = match self.map.get(pos) {
+                    Tile::Void => continue,
+                    Tile::Open => 2,
+                    Tile::Wall => 1,
+                };
+                image.fill_cell(x, y, scale, color(pos, brightness));
+            }
+        }
+        for &(pos, _) in path {
+            image.fill_cell(pos.0 as usize, pos.1 as usize, scale, color(pos, 4));
+        }
+        if let Some(&((x, y), _)) = path.last() {
+            image.fill_cell(x as usize, y as usize, scale, [255; 3]);
+        }
+        image
+    }
+}
+
+impl DenseMap {
+    fn new(lines: &[&str]) -> Self {
+        let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+        let mut tiles = vec![Tile::Void; width * lines.len()];
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.bytes().enumerate() {
+                tiles[y * width + x] = match c {
+                    b'.' => Tile::Open,
+                    b'#' => Tile::Wall,
+                    _ => Tile::Void,
+                };
             }
-            Up => (next_cube.top.0 + relative_coord, next_cube.bottom.1),
-            Left => (next_cube.bottom.0, next_cube.top.1 + relative_coord),
-            Right => (next_cube.top.0, next_cube.top.1 + relative_coord),
+        }
+        let mut map = Self {
+            width: width as isize,
+            height: lines.len() as isize,
+            tiles,
+            rows: vec![],
+            columns: vec![],
         };
-        (next_pos, next_facing)
+        map.rows = (0..map.height)
+            .map(|y| map.bounds((0..map.width).map(|x| (x, y))).0)
+            .collect();
+        map.columns = (0..map.width)
+            .map(|x| map.bounds((0..map.height).map(|y| (x, y))).1)
+            .collect();
+        map
+    }
+
+    /// First and last open or wall tile along a row or column.
+    fn bounds(
+        &self,
+        tiles: impl Iterator<Item = (isize, isize)>,
+    ) -> ((isize, isize), (isize, isize)) {
+        let (mut first, mut last) = (None, (0, 0));
+        for pos in tiles.filter(|&pos| self.get(pos) != Tile::Void) {
+            first.get_or_insert(pos);
+            last = pos;
+        }
+        let first = first.unwrap_or((0, 0));
+        ((first.0, last.0), (first.1, last.1))
+    }
+
+    fn get(&self, (x, y): (isize, isize)) -> Tile {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return Tile::Void;
+        }
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    /// The next tile in a direction, wrapping around to the other end of the
+    /// row or column.
+    fn step(&self, (x, y): (isize, isize), facing: Facing) -> (isize, isize) {
+        let (row, column) = (self.rows[y as usize], self.columns[x as usize]);
+        match facing {
+            Facing::Right if x == row.1 => (row.0, y),
+            Facing::Left if x == row.0 => (row.1, y),
+            Facing::Down if y == column.1 => (x, column.0),
+            Facing::Up if y == column.0 => (x, column.1),
+            _ => {
+                let pos_mod = facing.get_pos_mod();
+                (x + pos_mod.0, y + pos_mod.1)
+            }
+        }
     }
 }
 
-impl Rect {
-    const fn new(top_x: isize, top_y: isize, bottom_x: isize, bottom_y: isize) -> Self {
-        Self {
-            top: (top_x, top_y),
-            bottom: (bottom_x - 1, bottom_y - 1),
+impl Cube {
+    /// Folds the faces at the given positions on the map, in face units. Returns
+    /// `None` if they don't form a cube net.
+    fn fold(size: isize, blocks: &[(isize, isize)]) -> Option<Self> {
+        let mut faces: Vec<Face> = vec![Face {
+            origin: (blocks[0].0 * size, blocks[0].1 * size),
+            normal: [0, 0, -1],
+            right: [1, 0, 0],
+            down: [0, 1, 0],
+        }];
+        let mut stack = vec![(blocks[0], faces[0])];
+        while let Some(((bx, by), face)) = stack.pop() {
+            // Rolling the cube over an edge of the current face
+            let neighbors = [
+                ((bx + 1, by), [face.right, neg(face.normal), face.down]),
+                ((bx - 1, by), [neg(face.right), face.normal, face.down]),
+                ((bx, by + 1), [face.down, face.right, neg(face.normal)]),
+                ((bx, by - 1), [neg(face.down), face.right, face.normal]),
+            ];
+            for (block, [normal, right, down]) in neighbors {
+                let origin = (block.0 * size, block.1 * size);
+                if blocks.contains(&block) && faces.iter().all(|f| f.origin != origin) {
+                    let face = Face {
+                        origin,
+                        normal,
+                        right,
+                        down,
+                    };
+                    faces.push(face);
+                    stack.push((block, face));
+                }
+            }
         }
+        let mut normals = faces.iter().map(|f| f.normal).collect::<Vec<_>>();
+        normals.sort();
+        normals.dedup();
+        (faces.len() == 6 && normals.len() == 6).then_some(Self { size, faces })
+    }
+
+    fn face_of(&self, pos: (isize, isize)) -> Option<usize> {
+        let origin = (
+            pos.0 - pos.0.rem_euclid(self.size),
+            pos.1 - pos.1.rem_euclid(self.size),
+        );
+        self.faces.iter().position(|f| f.origin == origin)
+    }
+
+    /// Steps over the edge of the face in 3D, where the position is the center of
+    /// the tile with the cube spanning `-size..=size` on each axis.
+    fn wrap(&self, pos: (isize, isize), facing: Facing) -> ((isize, isize), Facing) {
+        let face = self.faces[self.face_of(pos).unwrap()];
+        let (x, y) = (pos.0 - face.origin.0, pos.1 - face.origin.1);
+        let s = self.size;
+        let point = add(
+            add(scale(face.normal, s), scale(face.right, 2 * x + 1 - s)),
+            scale(face.down, 2 * y + 1 - s),
+        );
+        let direction = face.direction(facing);
+        let next = *self.faces.iter().find(|f| f.normal == direction).unwrap();
+        // Over the edge, we move away from the face we left
+        let point = add(point, sub(direction, face.normal));
+        let next_facing = [Facing::Right, Facing::Down, Facing::Left, Facing::Up]
+            .into_iter()
+            .find(|&f| next.direction(f) == neg(face.normal))
+            .unwrap();
+        let x = (dot(point, next.right) + s - 1) / 2;
+        let y = (dot(point, next.down) + s - 1) / 2;
+        ((next.origin.0 + x, next.origin.1 + y), next_facing)
     }
+}
 
-    fn is_inside(&self, point: (isize, isize)) -> bool {
-        point.0 >= self.top.0
-            && point.0 <= self.bottom.0
-            && point.1 >= self.top.1
-            && point.1 <= self.bottom.1
+impl Face {
+    fn direction(&self, facing: Facing) -> Vec3 {
+        match facing {
+            Facing::Right => self.right,
+            Facing::Down => self.down,
+            Facing::Left => neg(self.right),
+            Facing::Up => neg(self.down),
+        }
     }
 }
 
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    add(a, neg(b))
+}
+
+fn neg(a: Vec3) -> Vec3 {
+    scale(a, -1)
+}
+
+fn scale(a: Vec3, k: isize) -> Vec3 {
+    a.map(|c| c * k)
+}
+
+fn dot(a: Vec3, b: Vec3) -> isize {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
 fn wrap(coord: isize, ranges: &[&RangeInclusive<isize>]) -> isize {
     let min = ranges.iter().map(|r| r.start()).min().unwrap();
     let max = ranges.iter().map(|r| r.end()).max().unwrap();
@@ -224,6 +457,15 @@ fn wrap(coord: isize, ranges: &[&RangeInclusive<isize>]) -> isize {
 }
 
 impl Facing {
+    fn symbol(&self) -> char {
+        match self {
+            Self::Up => '^',
+            Self::Down => 'v',
+            Self::Left => '<',
+            Self::Right => '>',
+        }
+    }
+
     fn get_pos_mod(&self) -> (isize, isize) {
         match self {
             Self::Up => (0, -1),
@@ -248,11 +490,16 @@ impl Facing {
 }
 
 #[aoc_generator(day22)]
-fn parse(input: &str) -> Grid {
+pub fn parse(input: &str) -> Grid {
     let mut walls = FxHashSet::default();
     let mut bounds = Vec::new();
     let (mut last_y, mut last_bound) = (isize::MAX, isize::MIN..=isize::MIN);
     let mut start = (isize::MIN, isize::MIN);
+    let mut tiles = vec![];
+    let lines = input
+        .lines()
+        .take_while(|l| !l.trim().is_empty())
+        .collect::<Vec<_>>();
     for (y, line) in input
         .lines()
         .enumerate()
@@ -266,6 +513,7 @@ fn parse(input: &str) -> Grid {
             if start.0 == isize::MIN {
                 start = (x, y);
             }
+            tiles.push((x, y));
             min_x = min_x.min(x);
             max_x = max_x.max(x);
             if c == '#' {
@@ -295,61 +543,68 @@ fn parse(input: &str) -> Grid {
         })
         .collect();
     bounds.remove(0);
+
     Grid {
         walls,
         bounds,
         starting_pos: start,
         directions,
-        edges: EDGES.into_iter().collect(),
-        faces: if last_y > 16 { RANGES } else { RANGES_EXAMPLE }
-            .into_iter()
-            .collect(),
+        cube: fold_tiles(&tiles),
+        map: DenseMap::new(&lines),
     }
 }
 
-#[aoc(day22, part1)]
-pub fn part1(input: &Grid) -> isize {
-    let mut pos = input.starting_pos;
-    let mut facing = Facing::Right;
-    for direction in &input.directions {
-        match direction {
-            Direction::Turn(r) => facing = facing.turn(*r),
-            Direction::Move(n) => {
-                for _ in 0..*n {
-                    match input.get_next_pos(pos, facing) {
-                        Some(new) => pos = new,
-                        None => break,
-                    }
-                }
-            }
+/// Splits the tiles into 6 square faces and folds them.
+fn fold_tiles(tiles: &[(isize, isize)]) -> Result<Cube, CubeError> {
+    let size = (1..)
+        .find(|s| 6 * s * s >= tiles.len() as isize)
+        .filter(|s| 6 * s * s == tiles.len() as isize)
+        .ok_or(CubeError::TileCount(tiles.len()))?;
+    let mut blocks = tiles
+        .iter()
+        .map(|&(x, y)| (x / size, y / size))
+        .collect::<Vec<_>>();
+    blocks.sort_by_key(|&(x, y)| (y, x));
+    blocks.dedup();
+    if blocks.len() != 6 {
+        return Err(CubeError::Faces(blocks.len()));
+    }
+    Cube::fold(size, &blocks).ok_or(CubeError::NotNet)
+}
+
+impl Display for CubeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TileCount(n) => write!(f, "{n} tiles can't make 6 square faces"),
+            Self::Faces(n) => write!(f, "the tiles cover {n} faces instead of 6"),
+            Self::NotNet => write!(f, "the map isn't a cube net"),
         }
     }
+}
+
+impl std::error::Error for CubeError {}
+
+#[aoc(day22, part1)]
+pub fn part1(input: &Grid) -> isize {
+    input.walk(
+        |pos, facing| Some((input.get_next_pos(pos, facing)?, facing)),
+        |_, _| {},
+    )
+}
 
-    1000 * (pos.1 + 1) + 4 * (pos.0 + 1) + facing as isize
+pub fn part1_ranges(input: &Grid) -> isize {
+    input.walk(
+        |pos, facing| Some((input.get_next_pos_ranges(pos, facing)?, facing)),
+        |_, _| {},
+    )
 }
 
 #[aoc(day22, part2)]
 pub fn part2(input: &Grid) -> isize {
-    let mut pos = input.starting_pos;
-    let mut facing = Facing::Right;
-    for direction in &input.directions {
-        match direction {
-            Direction::Turn(r) => facing = facing.turn(*r),
-            Direction::Move(n) => {
-                for _ in 0..*n {
-                    match input.get_next_cube_pos(pos, facing) {
-                        Some((new_pos, new_facing)) => {
-                            pos = new_pos;
-                            facing = new_facing;
-                        }
-                        None => break,
-                    }
-                }
-            }
-        }
-    }
-
-    1000 * (pos.1 + 1) + 4 * (pos.0 + 1) + facing as isize
+    input.walk(
+        |pos, facing| input.get_next_cube_pos(pos, facing),
+        |_, _| {},
+    )
 }
 
 #[cfg(test)]
@@ -373,6 +628,7 @@ mod tests {
 
 10R5L5R10L4R5L5"#;
         assert_eq!(part1(&parse(input)), 6032);
+        assert_eq!(part1_ranges(&parse(input)), 6032);
     }
 
     #[test]
@@ -398,4 +654,174 @@ mod tests {
 10R5L5R10L4R5L5"#;
         assert_eq!(part2(&parse(input)), 10006);
     }
+
+    #[test]
+    fn part2_puzzle_example() {
+        let input = r#"        ...#
+        .#..
+        #...
+        ....
+...#.......#
+........#...
+..#....#....
+..........#.
+        ...#....
+        .....#..
+        .#......
+        ......#.
+
+10R5L5R10L4R5L5"#;
+        assert_eq!(part2(&parse(input)), 5031);
+    }
+
+    #[test]
+    fn not_a_cube() {
+        let grid = parse("....\n..#.\n....\n....\n\n10R5L2");
+        assert_eq!(grid.check_cube(), Err(CubeError::TileCount(16)));
+        assert_eq!(part1(&grid), part1_ranges(&grid));
+        // Blocked by the wall right after turning down
+        assert_eq!(part1(&grid), 1000 + 4);
+
+        let grid = parse(&format!("{}\n\n5", ".".repeat(24)));
+        assert_eq!(grid.check_cube(), Err(CubeError::Faces(12)));
+        assert_eq!(part1(&grid), 1000 + 4 * 6);
+
+        let grid = parse("......\n......\n......\n......\n\n1");
+        assert_eq!(grid.check_cube(), Err(CubeError::NotNet));
+    }
+
+    #[test]
+    fn all_nets() {
+        // Grow every hexomino, keeping shapes with their top left corner at 0, 0
+        let normalize = |mut cells: Vec<(isize, isize)>| {
+            let min_x = cells.iter().map(|c| c.0).min().unwrap();
+            let min_y = cells.iter().map(|c| c.1).min().unwrap();
+            cells
+                .iter_mut()
+                .for_each(|c| *c = (c.0 - min_x, c.1 - min_y));
+            cells.sort_by_key(|&(x, y)| (y, x));
+            cells
+        };
+        let mut shapes = FxHashSet::from_iter([vec![(0, 0)]]);
+        for _ in 1..6 {
+            shapes = shapes
+                .iter()
+                .flat_map(|shape| {
+                    shape.iter().flat_map(move |&(x, y)| {
+                        [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+                            .into_iter()
+                            .filter(|cell| !shape.contains(cell))
+                            .map(|cell| {
+                                let mut shape = shape.clone();
+                                shape.push(cell);
+                                shape
+                            })
+                    })
+                })
+                .map(normalize)
+                .collect();
+        }
+        assert_eq!(shapes.len(), 216);
+
+        let size = 3;
+        let nets = shapes
+            .into_iter()
+            .filter_map(|blocks| Cube::fold(size, &blocks).map(|cube| (blocks, cube)))
+            .collect::<Vec<_>>();
+        // Up to rotations and reflections
+        let free = nets
+            .iter()
+            .map(|(blocks, _)| {
+                (0..8)
+                    .map(|symmetry| {
+                        let cells = blocks.iter().map(|&(x, y)| {
+                            let (x, y) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+                            let x = if symmetry & 1 != 0 { -x } else { x };
+                            let y = if symmetry & 2 != 0 { -y } else { y };
+                            (x, y)
+                        });
+                        normalize(cells.collect())
+                    })
+                    .min()
+                    .unwrap()
+            })
+            .collect::<FxHashSet<_>>();
+        assert_eq!(free.len(), 11);
+
+        // Going over an edge and coming back leads to the same tile
+        for (_, cube) in &nets {
+            for face in &cube.faces {
+                for i in 0..size {
+                    let (x, y) = face.origin;
+                    for (pos, facing) in [
+                        ((x + i, y), Facing::Up),
+                        ((x + i, y + size - 1), Facing::Down),
+                        ((x, y + i), Facing::Left),
+                        ((x + size - 1, y + i), Facing::Right),
+                    ] {
+                        let (next, next_facing) = cube.wrap(pos, facing);
+                        assert_ne!(cube.face_of(next), cube.face_of(pos));
+                        let back = cube.wrap(next, next_facing.turn(true).turn(true));
+                        assert_eq!(back, (pos, facing.turn(true).turn(true)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn render_example() {
+        let input = r#"        ...#
+        .#..
+        #...
+        ....
+...#.......#
+........#...
+..#....#....
+..........#.
+        ...#....
+        .....#..
+        .#......
+        ......#.
+
+10R5L5R10L4R5L5"#;
+        let grid = parse(input);
+        let expected = r#"        >>v#
+        .#v.
+        #.v.
+        ..v.
+...#...v..v#
+>>>v...>#.>>
+..#v...#....
+...>>>>v..#.
+        ...#....
+        .....#..
+        .#......
+        ......#.
+"#;
+        assert_eq!(grid.render(&grid.path(false)), expected);
+
+        let expected = r#"        >>v#
+        .#v.
+        #.v.
+        ..v.
+...#..^...v#
+.>>>>>^.#.>>
+.^#....#....
+.^........#.
+        ...#..v.
+        .....#v.
+        .#v<<<<.
+        ..v...#.
+"#;
+        let path = grid.path(true);
+        assert_eq!(grid.render(&path), expected);
+
+        let image = grid.render_faces(&path, 2);
+        assert_eq!((image.width(), image.height()), (32, 24));
+        assert_eq!(image.get(0, 0), [0; 3]);
+        assert_eq!(image.get(31, 23), [127, 64, 64]);
+        // The path ends in row 5, column 7, like in the puzzle
+        assert_eq!(image.get(12, 8), [255; 3]);
+    }
 }
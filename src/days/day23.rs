@@ -1,13 +1,85 @@
+use std::{
+    iter,
+    ops::{BitAnd, BitOr, BitOrAssign, Not, Range},
+};
+
 use fxhash::{FxHashMap, FxHashSet};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Grid {
     elves: FxHashSet<(i32, i32)>,
-    directions: [Direction; 4],
+    directions: Vec<Direction>,
+    rules: Rules,
+}
+
+/// The elves after a round.
+#[derive(Clone, Debug)]
+pub struct Round {
+    // Starting from 1
+    pub number: usize,
+    pub elves: FxHashSet<(i32, i32)>,
+    pub moves: usize,
+    pub bounds: Bounds,
+}
+
+/// Rounds simulated on a [`BitGrid`], with the grid's rules.
+pub struct Rounds {
+    grid: BitGrid,
+    number: usize,
+}
+
+/// Smallest rectangle containing every elf, corners included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+// An elf and the rank of the direction it proposes to go
+type Proposal = ((i32, i32), usize);
+
+/// How elves pick where to go, so that variants of the puzzle's process can
+/// be compared. The default is the puzzle's rules.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rules {
+    // Directions in the order they're considered on the first round
+    pub priority: Vec<Direction>,
+    pub rotation: Rotation,
+    pub neighborhood: Neighborhood,
+    pub conflicts: Conflicts,
+}
+
+/// How the direction priority changes after each round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Fixed,
+    FirstToLast,
+    LastToFirst,
+}
+
+/// Cells that must be free to move in a direction. An elf doesn't move if
+/// they are free in every direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighborhood {
+    // Only the destination
+    Line,
+    // The destination and the cells on both sides of it
+    Cone,
+    // The destination and two cells on each side of it
+    Wide,
+}
+
+/// What happens when several elves propose the same destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflicts {
+    // None of them move
+    Blocked,
+    // The elf whose direction comes first in the current priority moves
+    PriorityWins,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Direction {
+pub enum Direction {
     North,
     South,
     East,
@@ -15,16 +87,25 @@ enum Direction {
 }
 
 impl Grid {
+    pub fn with_rules(&self, rules: Rules) -> Self {
+        Self {
+            elves: self.elves.clone(),
+            directions: rules.priority.clone(),
+            rules,
+        }
+    }
+
     fn get_elf_moves(
         &self,
         elf: (i32, i32),
-        destinations: &mut FxHashMap<(i32, i32), Vec<(i32, i32)>>,
+        destinations: &mut FxHashMap<(i32, i32), Vec<Proposal>>,
     ) {
         let direction = self
             .directions
             .iter()
-            .find(|dir| !self.check_obstructed(elf, **dir));
-        if let Some(&direction) = direction {
+            .position(|dir| !self.check_obstructed(elf, *dir));
+        if let Some(rank) = direction {
+            let direction = self.directions[rank];
             if self
                 .directions
                 .iter()
@@ -34,37 +115,518 @@ impl Grid {
             }
             let pos_mod = direction.get_pos_mod();
             let dest = (elf.0 + pos_mod.0, elf.1 + pos_mod.1);
-            destinations.entry(dest).or_default().push(elf);
+            destinations.entry(dest).or_default().push((elf, rank));
         }
     }
 
     fn check_obstructed(&self, source: (i32, i32), direction: Direction) -> bool {
         let pos_mod = direction.get_pos_mod();
         let dest = (source.0 + pos_mod.0, source.1 + pos_mod.1);
+        let spread = match self.rules.neighborhood {
+            Neighborhood::Line => 0,
+            Neighborhood::Cone => 1,
+            Neighborhood::Wide => 2,
+        };
         match direction {
             Direction::North | Direction::South => {
-                (-1..=1).any(|m| self.elves.contains(&(dest.0 + m, dest.1)))
+                (-spread..=spread).any(|m| self.elves.contains(&(dest.0 + m, dest.1)))
             }
             Direction::East | Direction::West => {
-                (-1..=1).any(|m| self.elves.contains(&(dest.0, dest.1 + m)))
+                (-spread..=spread).any(|m| self.elves.contains(&(dest.0, dest.1 + m)))
             }
         }
     }
 
-    fn run_turn(&mut self) -> bool {
+    /// Runs a round, returning whether any elf moved.
+    pub fn run_turn(&mut self) -> bool {
+        self.step() > 0
+    }
+
+    /// Runs a round, returning how many elves moved.
+    fn step(&mut self) -> usize {
         let mut destinations =
             FxHashMap::with_capacity_and_hasher(self.elves.len(), fxhash::FxBuildHasher::default());
         for elf in &self.elves {
             self.get_elf_moves(*elf, &mut destinations);
         }
-        let mut moved = false;
-        for (destination, sources) in destinations.into_iter().filter(|(_, v)| v.len() == 1) {
-            self.elves.remove(&sources[0]);
+        let mut moves = 0;
+        for (destination, sources) in destinations {
+            let source = match self.rules.conflicts {
+                Conflicts::Blocked if sources.len() > 1 => continue,
+                Conflicts::Blocked => sources[0].0,
+                // Elves going in the same direction can't have the same destination
+                Conflicts::PriorityWins => sources.iter().min_by_key(|(_, rank)| rank).unwrap().0,
+            };
+            self.elves.remove(&source);
             self.elves.insert(destination);
-            moved = true;
+            moves += 1;
+        }
+        match self.rules.rotation {
+            Rotation::Fixed => {}
+            Rotation::FirstToLast => self.directions.rotate_left(1),
+            Rotation::LastToFirst => self.directions.rotate_right(1),
+        }
+        moves
+    }
+
+    pub fn empty_tiles(&self) -> i32 {
+        Bounds::of(&self.elves).area() - self.elves.len() as i32
+    }
+
+    /// The state after each round, forever.
+    pub fn rounds(&self) -> Rounds {
+        Rounds {
+            grid: BitGrid::from(self),
+            number: 0,
+        }
+    }
+
+    /// The elves in the puzzle's format, over their bounding box.
+    pub fn render(&self) -> String {
+        render(&self.elves, Bounds::of(&self.elves))
+    }
+
+    /// Rounds until no elf moves, if that happens within `limit` rounds.
+    pub fn rounds_until_settled(&self, limit: usize) -> Option<usize> {
+        let mut grid = self.clone();
+        (1..=limit).find(|_| !grid.run_turn())
+    }
+}
+
+impl Iterator for Rounds {
+    type Item = Round;
+
+    fn next(&mut self) -> Option<Round> {
+        let moves = self.grid.step();
+        self.number += 1;
+        let elves = self.grid.elves().collect();
+        Some(Round {
+            number: self.number,
+            bounds: Bounds::of(&elves),
+            elves,
+            moves,
+        })
+    }
+}
+
+impl Round {
+    pub fn empty_tiles(&self) -> i32 {
+        self.bounds.area() - self.elves.len() as i32
+    }
+
+    /// Renders the elves over any area, to compare with the puzzle's drawings.
+    pub fn render(&self, bounds: Bounds) -> String {
+        render(&self.elves, bounds)
+    }
+}
+
+impl Bounds {
+    pub fn of(elves: &FxHashSet<(i32, i32)>) -> Self {
+        let min_x = elves.iter().map(|(x, _)| *x).min().unwrap();
+        let max_x = elves.iter().map(|(x, _)| *x).max().unwrap();
+        let min_y = elves.iter().map(|(_, y)| *y).min().unwrap();
+        let max_y = elves.iter().map(|(_, y)| *y).max().unwrap();
+        Self {
+            min: (min_x, min_y),
+            max: (max_x, max_y),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.max.0 + 1 - self.min.0
+    }
+
+    pub fn height(&self) -> i32 {
+        self.max.1 + 1 - self.min.1
+    }
+
+    pub fn area(&self) -> i32 {
+        self.width() * self.height()
+    }
+}
+
+fn render(elves: &FxHashSet<(i32, i32)>, bounds: Bounds) -> String {
+    (bounds.min.1..=bounds.max.1)
+        .map(|y| {
+            let mut line = (bounds.min.0..=bounds.max.0)
+                .map(|x| if elves.contains(&(x, y)) { '#' } else { '.' })
+                .collect::<String>();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            priority: vec![
+                Direction::North,
+                Direction::South,
+                Direction::West,
+                Direction::East,
+            ],
+            rotation: Rotation::FirstToLast,
+            neighborhood: Neighborhood::Cone,
+            conflicts: Conflicts::Blocked,
+        }
+    }
+}
+
+/// Elves stored as row bitmasks, so that a round is a few shifts and masks
+/// over the whole grid. Rows and columns are added whenever the elves get near
+/// an edge, so they can spread out as far as they like.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitGrid {
+    // With `PADDING` empty rows at the top and bottom, and `PADDING` empty
+    // columns on the left and right
+    cells: Plane,
+    // Position of the first column of the first row
+    origin: (i32, i32),
+    directions: Vec<Direction>,
+    rules: Rules,
+}
+
+// Rows and columns around the elves, enough for the widest neighborhood
+const PADDING: usize = 2;
+
+/// A set of cells, as rows of `words` words where bit `x % 64` of word `x / 64`
+/// is set if column `x` is in the set.
+///
+/// Shifting cells east or west carries them over the end of their row into the
+/// next one. That can't happen to cells that are `PADDING` columns clear of
+/// the sides, like the elves, as long as they're shifted at most that far.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Plane {
+    bits: Vec<u64>,
+    words: usize,
+}
+
+impl Plane {
+    fn new(height: usize, words: usize) -> Self {
+        Self {
+            bits: vec![0; height * words],
+            words,
+        }
+    }
+
+    /// A set with no cells, of the same size.
+    fn empty(&self) -> Self {
+        Self::new(self.height(), self.words)
+    }
+
+    fn height(&self) -> usize {
+        self.bits.len() / self.words
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[u64]> {
+        self.bits.chunks(self.words)
+    }
+
+    fn insert(&mut self, x: usize, y: usize) {
+        self.bits[y * self.words + x / 64] |= 1 << (x % 64);
+    }
+
+    fn count(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    fn rows_are_empty(&self, rows: Range<usize>) -> bool {
+        self.bits[rows.start * self.words..rows.end * self.words]
+            .iter()
+            .all(|&w| w == 0)
+    }
+
+    /// Adds an empty row at the top, or at the bottom.
+    fn add_row(&mut self, top: bool) {
+        let row = iter::repeat_n(0, self.words);
+        if top {
+            self.bits.splice(0..0, row);
+        } else {
+            self.bits.extend(row);
+        }
+    }
+
+    /// Adds a word of empty columns on the left, or on the right.
+    fn add_columns(&mut self, left: bool) {
+        let words = self.words + 1;
+        let mut bits = Vec::with_capacity(self.height() * words);
+        for row in self.rows() {
+            if left {
+                bits.push(0);
+            }
+            bits.extend_from_slice(row);
+            if !left {
+                bits.push(0);
+            }
+        }
+        *self = Self { bits, words };
+    }
+
+    /// Moves every cell one column east, so bit `x` tells if there's a cell
+    /// west of `x`.
+    fn east(&self) -> Self {
+        let carries = iter::once(0).chain(self.bits.iter().map(|w| w >> 63));
+        let bits = self
+            .bits
+            .iter()
+            .zip(carries)
+            .map(|(w, carry)| w << 1 | carry);
+        Self {
+            bits: bits.collect(),
+            ..*self
+        }
+    }
+
+    /// Moves every cell one column west.
+    fn west(&self) -> Self {
+        let carries = self.bits[1..].iter().map(|w| w << 63).chain(iter::once(0));
+        let bits = self
+            .bits
+            .iter()
+            .zip(carries)
+            .map(|(w, carry)| w >> 1 | carry);
+        Self {
+            bits: bits.collect(),
+            ..*self
+        }
+    }
+
+    /// Moves every cell one row north, dropping the first row.
+    fn north(&self) -> Self {
+        let mut bits = self.bits[self.words..].to_vec();
+        bits.resize(self.bits.len(), 0);
+        Self { bits, ..*self }
+    }
+
+    /// Moves every cell one row south, dropping the last row.
+    fn south(&self) -> Self {
+        let mut bits = vec![0; self.words];
+        bits.extend_from_slice(&self.bits[..self.bits.len() - self.words]);
+        Self { bits, ..*self }
+    }
+
+    /// Cells that are in `self` but not in `other`.
+    fn and_not(mut self, other: &Self) -> Self {
+        for (a, b) in self.bits.iter_mut().zip(&other.bits) {
+            *a &= !b;
+        }
+        self
+    }
+
+    /// Columns and rows of the cells, row by row.
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.bits.iter().enumerate().flat_map(move |(i, &word)| {
+            let (x0, y) = (i % self.words * 64, i / self.words);
+            (0..64)
+                .filter(move |b| word >> b & 1 != 0)
+                .map(move |b| (x0 + b, y))
+        })
+    }
+}
+
+impl BitAnd<&Plane> for Plane {
+    type Output = Self;
+
+    fn bitand(mut self, other: &Plane) -> Self {
+        for (a, b) in self.bits.iter_mut().zip(&other.bits) {
+            *a &= b;
+        }
+        self
+    }
+}
+
+impl BitOr<&Plane> for Plane {
+    type Output = Self;
+
+    fn bitor(mut self, other: &Plane) -> Self {
+        self |= other;
+        self
+    }
+}
+
+impl BitOrAssign<&Plane> for Plane {
+    fn bitor_assign(&mut self, other: &Plane) {
+        for (a, b) in self.bits.iter_mut().zip(&other.bits) {
+            *a |= b;
+        }
+    }
+}
+
+impl Not for Plane {
+    type Output = Self;
+
+    fn not(mut self) -> Self {
+        for w in &mut self.bits {
+            *w = !*w;
+        }
+        self
+    }
+}
+
+impl BitGrid {
+    pub fn new(elves: impl IntoIterator<Item = (i32, i32)>, rules: Rules) -> Self {
+        let elves = elves.into_iter().collect::<Vec<_>>();
+        let min_y = elves.iter().map(|e| e.1).min().unwrap_or(0);
+        let max_y = elves.iter().map(|e| e.1).max().unwrap_or(0);
+        let min_x = elves.iter().map(|e| e.0).min().unwrap_or(0);
+        let max_x = elves.iter().map(|e| e.0).max().unwrap_or(0);
+        let width = (max_x - min_x + 1) as usize;
+        let words = (width + 2 * PADDING).div_ceil(64);
+        // Centered, so the elves can spread both ways before columns are added
+        let offset_x = ((words * 64 - width) / 2) as i32 - min_x;
+        let mut cells = Plane::new((max_y - min_y + 1) as usize + 2 * PADDING, words);
+        for (x, y) in elves {
+            cells.insert((x + offset_x) as usize, (y - min_y) as usize + PADDING);
+        }
+        Self {
+            cells,
+            origin: (-offset_x, min_y - PADDING as i32),
+            directions: rules.priority.clone(),
+            rules,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs a round, returning whether any elf moved.
+    pub fn run_turn(&mut self) -> bool {
+        self.step() > 0
+    }
+
+    /// Adds rows and columns until there are `PADDING` empty ones on every side.
+    fn make_room(&mut self) {
+        while !self.cells.rows_are_empty(0..PADDING) {
+            self.cells.add_row(true);
+            self.origin.1 -= 1;
+        }
+        while !self
+            .cells
+            .rows_are_empty(self.cells.height() - PADDING..self.cells.height())
+        {
+            self.cells.add_row(false);
+        }
+        let left = (1 << PADDING) - 1;
+        if self.cells.rows().any(|row| row[0] & left != 0) {
+            self.cells.add_columns(true);
+            self.origin.0 -= 64;
+        }
+        let right = left << (64 - PADDING);
+        if self.cells.rows().any(|row| row[row.len() - 1] & right != 0) {
+            self.cells.add_columns(false);
+        }
+    }
+
+    /// Runs a round, returning how many elves moved.
+    fn step(&mut self) -> usize {
+        self.make_room();
+        let elves = &self.cells;
+        let neighborhood = self.rules.neighborhood;
+        // Cells on both sides of each elf's column
+        let spread = |cells: Plane| match neighborhood {
+            Neighborhood::Line => cells,
+            Neighborhood::Cone => cells.east() | &cells.west() | &cells,
+            Neighborhood::Wide => {
+                let (east, west) = (cells.east(), cells.west());
+                east.east() | &west.west() | &east | &west | &cells
+            }
+        };
+        // Cells above and below each elf's row
+        let column = match neighborhood {
+            Neighborhood::Line => elves.clone(),
+            Neighborhood::Cone => elves.north() | &elves.south() | elves,
+            Neighborhood::Wide => {
+                let (north, south) = (elves.north(), elves.south());
+                north.north() | &south.south() | &north | &south | elves
+            }
+        };
+        // Directions where the cells are free, from each elf's point of view,
+        // in the order of `Direction`
+        let free = [
+            !spread(elves.south()),
+            !spread(elves.north()),
+            !column.west(),
+            !column.east(),
+        ];
+        let alone = self
+            .directions
+            .iter()
+            .fold(!elves.empty(), |acc, &d| acc & &free[d as usize]);
+        // Elves proposing to move in each direction
+        let mut remaining = elves.clone().and_not(&alone);
+        let mut proposals = [(); 4].map(|_| elves.empty());
+        for &direction in &self.directions {
+            // A direction that's listed twice gets no more elves the second
+            // time, so keep the ones it got
+            proposals[direction as usize] |= &(remaining.clone() & &free[direction as usize]);
+            remaining = remaining.and_not(&free[direction as usize]);
+        }
+
+        let rank = |d: usize| {
+            self.directions
+                .iter()
+                .position(|&direction| direction as usize == d)
+                .unwrap_or(4)
+        };
+        // Elves arriving at each tile, by the direction they move in
+        let [north, south, east, west] = &proposals;
+        let arrivals = [north.north(), south.south(), east.east(), west.west()];
+        let accepted: [Plane; 4] = std::array::from_fn(|d| {
+            let rivals = (0..4)
+                .filter(|&other| match self.rules.conflicts {
+                    Conflicts::Blocked => other != d,
+                    Conflicts::PriorityWins => rank(other) < rank(d),
+                })
+                .fold(elves.empty(), |acc, other| acc | &arrivals[other]);
+            arrivals[d].clone().and_not(&rivals)
+        });
+        let moves = accepted.iter().map(Plane::count).sum();
+        let next = accepted
+            .iter()
+            .fold(elves.clone(), |acc, cells| acc | cells);
+        // Elves that move leave their tile, which no other elf can move to
+        self.cells = next
+            .and_not(&accepted[Direction::North as usize].south())
+            .and_not(&accepted[Direction::South as usize].north())
+            .and_not(&accepted[Direction::East as usize].west())
+            .and_not(&accepted[Direction::West as usize].east());
+        match self.rules.rotation {
+            Rotation::Fixed => {}
+            Rotation::FirstToLast => self.directions.rotate_left(1),
+            Rotation::LastToFirst => self.directions.rotate_right(1),
+        }
+        moves
+    }
+
+    /// Elf positions, in the coordinates of the grid they came from.
+    pub fn elves(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let (x0, y0) = self.origin;
+        self.cells
+            .cells()
+            .map(move |(x, y)| (x as i32 + x0, y as i32 + y0))
+    }
+
+    pub fn empty_tiles(&self) -> i32 {
+        let min_x = self.elves().map(|(x, _)| x).min().unwrap();
+        let max_x = self.elves().map(|(x, _)| x).max().unwrap();
+        let min_y = self.elves().map(|(_, y)| y).min().unwrap();
+        let max_y = self.elves().map(|(_, y)| y).max().unwrap();
+        (max_x + 1 - min_x) * (max_y + 1 - min_y) - self.len() as i32
+    }
+}
+
+impl From<&Grid> for BitGrid {
+    fn from(grid: &Grid) -> Self {
+        Self {
+            directions: grid.directions.clone(),
+            ..Self::new(grid.elves.iter().copied(), grid.rules.clone())
         }
-        self.directions.rotate_left(1);
-        moved
     }
 }
 
@@ -80,7 +642,7 @@ impl Direction {
 }
 
 #[aoc_generator(day23)]
-fn parse(input: &str) -> Grid {
+pub fn parse(input: &str) -> Grid {
     let elves = input
         .lines()
         .enumerate()
@@ -90,33 +652,26 @@ fn parse(input: &str) -> Grid {
                 .filter_map(move |(x, c)| (c == '#').then_some((x as i32, y as i32)))
         })
         .collect();
+    let rules = Rules::default();
     Grid {
         elves,
-        directions: [
-            Direction::North,
-            Direction::South,
-            Direction::West,
-            Direction::East,
-        ],
+        directions: rules.priority.clone(),
+        rules,
     }
 }
 
 #[aoc(day23, part1)]
 pub fn part1(input: &Grid) -> i32 {
-    let mut grid = input.clone();
+    let mut grid = BitGrid::from(input);
     for _ in 0..10 {
         grid.run_turn();
     }
-    let min_x = grid.elves.iter().map(|(x, _)| x).min().unwrap();
-    let max_x = grid.elves.iter().map(|(x, _)| x).max().unwrap();
-    let min_y = grid.elves.iter().map(|(_, y)| y).min().unwrap();
-    let max_y = grid.elves.iter().map(|(_, y)| y).max().unwrap();
-    (max_x + 1 - min_x) * (max_y + 1 - min_y) - grid.elves.len() as i32
+    grid.empty_tiles()
 }
 
 #[aoc(day23, part2)]
 pub fn part2(input: &Grid) -> i32 {
-    let mut grid = input.clone();
+    let mut grid = BitGrid::from(input);
     for i in 1.. {
         if !grid.run_turn() {
             return i;
@@ -125,9 +680,22 @@ pub fn part2(input: &Grid) -> i32 {
     unreachable!()
 }
 
+pub fn part1_hash_set(input: &Grid) -> i32 {
+    let mut grid = input.clone();
+    for _ in 0..10 {
+        grid.run_turn();
+    }
+    grid.empty_tiles()
+}
+
+pub fn part2_hash_set(input: &Grid) -> i32 {
+    input.rounds_until_settled(usize::MAX).unwrap() as i32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::{cycle, random::Random};
 
     #[test]
     fn part1_example() {
@@ -152,4 +720,202 @@ mod tests {
 .#..#.."#;
         assert_eq!(part2(&parse(input)), 20);
     }
+
+    #[test]
+    fn part2_example_cycle() {
+        let input = r#"....#..
+..###.#
+#...#.#
+.#...##
+#.###..
+##.#.##
+.#..#.."#;
+        // Once the elves settle, only the direction order keeps rotating
+        let cycle = cycle::brent(parse(input), |grid| {
+            let mut grid = grid.clone();
+            grid.run_turn();
+            grid
+        });
+        assert_eq!(cycle.start + 1, 20);
+        assert_eq!(cycle.period, 4);
+    }
+
+    #[test]
+    fn implementations_agree() {
+        let mut random = Random::new(0x2022_1223);
+        let input = (0..40)
+            .map(|_| {
+                (0..40)
+                    .map(|_| if random.below(3) == 0 { '#' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let grid = parse(&input);
+        assert_eq!(part1(&grid), part1_hash_set(&grid));
+        assert_eq!(part2(&grid), part2_hash_set(&grid));
+        // Wider than two words of columns
+        let wide = parse(&"#".repeat(130));
+        assert_eq!(part1(&wide), part1_hash_set(&wide));
+        assert_eq!(part2(&wide), part2_hash_set(&wide));
+        for rules in variants() {
+            let mut grid = grid.with_rules(rules);
+            let mut bits = BitGrid::from(&grid);
+            for _ in 0..200 {
+                assert_eq!(bits.step(), grid.step());
+            }
+            assert_eq!(bits.elves().collect::<FxHashSet<_>>(), grid.elves);
+        }
+    }
+
+    fn variants() -> Vec<Rules> {
+        vec![
+            Rules {
+                rotation: Rotation::Fixed,
+                ..Rules::default()
+            },
+            Rules {
+                rotation: Rotation::LastToFirst,
+                priority: vec![
+                    Direction::East,
+                    Direction::West,
+                    Direction::South,
+                    Direction::North,
+                ],
+                ..Rules::default()
+            },
+            Rules {
+                neighborhood: Neighborhood::Line,
+                ..Rules::default()
+            },
+            Rules {
+                neighborhood: Neighborhood::Wide,
+                conflicts: Conflicts::PriorityWins,
+                ..Rules::default()
+            },
+            Rules {
+                neighborhood: Neighborhood::Line,
+                conflicts: Conflicts::PriorityWins,
+                ..Rules::default()
+            },
+            Rules {
+                priority: vec![Direction::North, Direction::North, Direction::South],
+                ..Rules::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn rule_variants() {
+        let input = r#"....#..
+..###.#
+#...#.#
+.#...##
+#.###..
+##.#.##
+.#..#.."#;
+        let grid = parse(input);
+        assert_eq!(grid.with_rules(Rules::default()), grid);
+        assert_eq!(grid.rounds_until_settled(100), Some(20));
+
+        // The elves in the middle both want to go to the empty tile
+        let grid = parse(".#.\n.#.\n...\n.#.\n.#.");
+        let after_round = |conflicts| {
+            let mut grid = grid.with_rules(Rules {
+                conflicts,
+                ..Rules::default()
+            });
+            grid.run_turn();
+            let mut elves = grid.elves.into_iter().collect::<Vec<_>>();
+            elves.sort();
+            elves
+        };
+        assert_eq!(
+            after_round(Conflicts::Blocked),
+            [(1, -1), (1, 1), (1, 3), (1, 5)]
+        );
+        assert_eq!(
+            after_round(Conflicts::PriorityWins),
+            [(1, -1), (1, 1), (1, 2), (1, 5)]
+        );
+
+        let grid = parse(input);
+        for rules in variants() {
+            let mut variant = grid.with_rules(rules);
+            let mut bits = BitGrid::from(&variant);
+            for _ in 0..10 {
+                assert_eq!(bits.step(), variant.step());
+                assert_eq!(bits.elves().collect::<FxHashSet<_>>(), variant.elves);
+            }
+            assert_eq!(variant.elves.len(), grid.elves.len());
+            assert_ne!(variant.elves, {
+                let mut grid = grid.clone();
+                (0..10).for_each(|_| {
+                    grid.run_turn();
+                });
+                grid.elves
+            });
+        }
+    }
+
+    #[test]
+    fn rounds_example() {
+        let grid = parse(".....\n..##.\n..#..\n.....\n..##.\n.....");
+        let frame = Bounds {
+            min: (0, 0),
+            max: (4, 5),
+        };
+        let expected = [
+            "..##.\n.....\n..#..\n...#.\n..#..\n.....\n",
+            ".....\n..##.\n.#...\n....#\n.....\n..#..\n",
+            "..#..\n....#\n#....\n....#\n.....\n..#..\n",
+        ];
+        let rounds = grid.rounds().take(4).collect::<Vec<_>>();
+        for (round, expected) in rounds.iter().zip(expected) {
+            assert_eq!(round.render(frame), expected);
+        }
+        assert_eq!(rounds[0].moves, 3);
+        assert_eq!(rounds[3].moves, 0);
+        assert_eq!(rounds[3].number, 4);
+        assert_eq!(grid.render(), "##\n#.\n..\n##\n");
+
+        let input = r#"....#..
+..###.#
+#...#.#
+.#...##
+#.###..
+##.#.##
+.#..#.."#;
+        let grid = parse(input);
+        let rounds = grid
+            .rounds()
+            .take_while(|round| round.moves > 0)
+            .collect::<Vec<_>>();
+        assert_eq!(rounds.len(), 19);
+        assert_eq!(rounds[9].empty_tiles(), 110);
+        assert_eq!(
+            rounds[9].render(rounds[9].bounds),
+            r#"......#.....
+..........#.
+.#.#..#.....
+.....#......
+..#.....#..#
+#......##...
+....##......
+.#........#.
+...#.#..#...
+............
+...#..#..#..
+"#
+        );
+        assert_eq!(rounds[18].bounds.width(), 14);
+
+        for rules in variants() {
+            let mut grid = grid.with_rules(rules);
+            for round in grid.rounds().take(10) {
+                assert_eq!(round.moves, grid.step());
+                assert_eq!(round.elves, grid.elves);
+            }
+        }
+    }
 }
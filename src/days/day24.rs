@@ -25,6 +25,20 @@ enum Direction {
     Right,
 }
 
+/// The blizzards as row bitmasks over the inner cells, precomputed for their
+/// whole cycle so that every reachable cell can be advanced at once each
+/// minute. Bit `x` of a row is column `x + 1` of the grid.
+#[derive(Clone, Debug)]
+pub struct Basin {
+    width: usize,
+    height: usize,
+    // Horizontal blizzards by minute (modulo the width), then by row
+    horizontal: Vec<Vec<u128>>,
+    // Vertical blizzards by starting row, they only move between rows
+    up: Vec<u128>,
+    down: Vec<u128>,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 struct Blizzard {
     initial_pos: (usize, usize),
@@ -91,6 +105,97 @@ impl Grid {
     }
 }
 
+impl Basin {
+    pub fn new(grid: &Grid) -> Self {
+        let (width, height) = (grid.width, grid.height);
+        assert!(width <= 128, "the basin is wider than 128 cells");
+        let full = u128::MAX >> (128 - width);
+        let masks = |direction| {
+            (1..=height)
+                .map(|y| {
+                    (1..=width)
+                        .filter(|&x| grid.cells[y][x] == Cell::Blizzard(direction))
+                        .fold(0u128, |acc, x| acc | 1 << (x - 1))
+                })
+                .collect::<Vec<_>>()
+        };
+        let (left, right) = (masks(Direction::Left), masks(Direction::Right));
+        let horizontal = (0..width)
+            .map(|minute| {
+                (0..height)
+                    .map(|y| {
+                        if minute == 0 {
+                            return left[y] | right[y];
+                        }
+                        let (l, r) = (left[y], right[y]);
+                        (l >> minute | l << (width - minute) | r << minute | r >> (width - minute))
+                            & full
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            horizontal,
+            up: masks(Direction::Up),
+            down: masks(Direction::Down),
+        }
+    }
+
+    fn blizzards(&self, y: usize, minute: usize) -> u128 {
+        let h = self.height;
+        self.horizontal[minute % self.width][y]
+            | self.up[(y + minute) % h]
+            | self.down[(y + h - minute % h) % h]
+    }
+
+    /// Minute at which the expedition reaches the other side, leaving the
+    /// entrance (or the exit, if `reverse`) at `start`.
+    pub fn crossing(&self, start: usize, reverse: bool) -> usize {
+        let (w, h) = (self.width, self.height);
+        let full = u128::MAX >> (128 - w);
+        let (entry_row, entry, exit_row, exit) = if reverse {
+            (h - 1, 1 << (w - 1), 0, 1)
+        } else {
+            (0, 1, h - 1, 1 << (w - 1))
+        };
+        // Past this, every (cell, blizzard state) pair has been seen
+        let limit = start + w * h * lcm(w, h);
+        let mut rows = vec![0u128; h];
+        let mut next = vec![0u128; h];
+        let mut minute = start;
+        while rows[exit_row] & exit == 0 {
+            assert!(minute < limit, "end not reached");
+            minute += 1;
+            for (y, cell) in next.iter_mut().enumerate() {
+                let mut reachable = rows[y] | rows[y] << 1 | rows[y] >> 1;
+                if y > 0 {
+                    reachable |= rows[y - 1];
+                }
+                if y + 1 < h {
+                    reachable |= rows[y + 1];
+                }
+                if y == entry_row {
+                    // Waiting at the entrance is always possible
+                    reachable |= entry;
+                }
+                *cell = reachable & full & !self.blizzards(y, minute);
+            }
+            std::mem::swap(&mut rows, &mut next);
+        }
+        minute + 1
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
 impl Direction {
     fn get_pos_mod(&self) -> (isize, isize) {
         match self {
@@ -130,7 +235,7 @@ fn bfs(start: (usize, usize), grid: Grid, reverse: bool, step: usize) -> (usize,
 }
 
 #[aoc_generator(day24)]
-fn parse(input: &str) -> Grid {
+pub fn parse(input: &str) -> Grid {
     let cells: Vec<Vec<_>> = input
         .lines()
         .map(|l| {
@@ -156,12 +261,23 @@ fn parse(input: &str) -> Grid {
 
 #[aoc(day24, part1)]
 pub fn part1(input: &Grid) -> usize {
-    let grid = input.clone();
-    bfs((1, 0), grid, false, 0).0
+    Basin::new(input).crossing(0, false)
 }
 
 #[aoc(day24, part2)]
 pub fn part2(input: &Grid) -> usize {
+    let basin = Basin::new(input);
+    let there = basin.crossing(0, false);
+    let back = basin.crossing(there, true);
+    basin.crossing(back, false)
+}
+
+pub fn part1_hash_set(input: &Grid) -> usize {
+    let grid = input.clone();
+    bfs((1, 0), grid, false, 0).0
+}
+
+pub fn part2_hash_set(input: &Grid) -> usize {
     let grid = input.clone();
     let (steps_a, grid) = bfs((1, 0), grid, false, 0);
     let (steps_b, grid) = bfs((grid.width, grid.height + 1), grid, true, steps_a);
@@ -172,6 +288,7 @@ pub fn part2(input: &Grid) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::random::Random;
 
     #[test]
     fn part1_example() {
@@ -194,4 +311,38 @@ mod tests {
 ######.#"#;
         assert_eq!(part2(&parse(input)), 54);
     }
+
+    /// A basin of the given inner size, with random blizzards everywhere
+    /// except in the entrance and exit columns, like the real inputs.
+    fn generate(width: usize, height: usize, seed: u64) -> String {
+        let mut random = Random::new(seed);
+        let mut lines = vec![format!("#.{}", "#".repeat(width))];
+        for _ in 0..height {
+            let row = (1..=width)
+                .map(|x| {
+                    let vertical = x != 1 && x != width;
+                    match random.below(6) {
+                        1 => '>',
+                        2 => '<',
+                        3 if vertical => '^',
+                        4 if vertical => 'v',
+                        _ => '.',
+                    }
+                })
+                .collect::<String>();
+            lines.push(format!("#{row}#"));
+        }
+        lines.push(format!("{}.#", "#".repeat(width)));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn implementations_agree() {
+        for (seed, (width, height)) in [(6, 4), (10, 5), (20, 7), (30, 12)].into_iter().enumerate()
+        {
+            let grid = parse(&generate(width, height, seed as u64 + 1));
+            assert_eq!(part1(&grid), part1_hash_set(&grid));
+            assert_eq!(part2(&grid), part2_hash_set(&grid));
+        }
+    }
 }
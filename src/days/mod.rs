@@ -5,10 +5,10 @@ mod day12;
 mod day13;
 mod day14;
 mod day15;
-mod day16;
-mod day17;
-mod day18;
-mod day19;
+pub mod day16;
+pub mod day17;
+pub mod day18;
+pub mod day19;
 mod day2;
 mod day3;
 mod day4;
@@ -17,9 +17,9 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
-mod day20;
-mod day21;
-mod day22;
-mod day23;
-mod day24;
+pub mod day20;
+pub mod day21;
+pub mod day22;
+pub mod day23;
+pub mod day24;
 mod day25;
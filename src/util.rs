@@ -1,5 +1,10 @@
 use std::{iter::FromIterator, ops::BitAnd};
 
+pub mod bigint;
+pub mod cycle;
+pub mod image;
+pub mod random;
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Default, Debug)]
 pub struct BitSet(u64);
 
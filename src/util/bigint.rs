@@ -0,0 +1,405 @@
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    ops::{Add, Div, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+/// Arbitrary-precision signed integer, for when `i64` isn't enough.
+///
+/// Division truncates towards zero, like it does for primitive integers.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct BigInt {
+    negative: bool,
+    // Base 2^32 digits, least significant first, without leading zeros
+    magnitude: Vec<u32>,
+}
+
+/// Returned when parsing something that isn't an optional `-` followed by
+/// decimal digits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseBigIntError(String);
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        Self {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let abs = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &d| acc << 32 | d as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut out = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for (i, &x) in a.iter().enumerate() {
+        let sum = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        out.push(sum as u32);
+        carry = sum >> 32;
+    }
+    out.push(carry as u32);
+    out
+}
+
+/// `a - b`, where `a >= b`.
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &x) in a.iter().enumerate() {
+        let diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        out.push(diff.rem_euclid(1 << 32) as u32);
+        borrow = (diff < 0) as i64;
+    }
+    out
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let product = x as u64 * y as u64 + out[i + j] as u64 + carry;
+            out[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        out[i + b.len()] = carry as u32;
+    }
+    out
+}
+
+fn div_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut out = vec![0; a.len()];
+    let mut rem = 0u64;
+    for (i, &x) in a.iter().enumerate().rev() {
+        let cur = rem << 32 | x as u64;
+        out[i] = (cur / d as u64) as u32;
+        rem = cur % d as u64;
+    }
+    (out, rem as u32)
+}
+
+/// Shifts left by `shift < 32` bits, with an extra digit for the carry.
+fn shl(a: &[u32], shift: u32) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for &x in a {
+        out.push(x << shift | carry);
+        carry = if shift == 0 { 0 } else { x >> (32 - shift) };
+    }
+    out.push(carry);
+    out
+}
+
+/// Long division one digit at a time (Knuth's algorithm D), where each digit
+/// of the quotient is estimated from the top digits and corrected at most
+/// twice.
+fn div(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if let [d] = b {
+        return div_small(a, *d).0;
+    }
+    if compare(a, b) == Ordering::Less {
+        return vec![];
+    }
+    // Normalize so that the top digit of the divisor has its high bit set,
+    // which keeps the estimates close
+    let shift = b.last().unwrap().leading_zeros();
+    let v = shl(b, shift);
+    let mut u = shl(a, shift);
+    let n = b.len();
+    let (top, next) = (v[n - 1] as u64, v[n - 2] as u64);
+    let mut quotient = vec![0; a.len() - n + 1];
+    for j in (0..quotient.len()).rev() {
+        let num = (u[j + n] as u64) << 32 | u[j + n - 1] as u64;
+        let (mut q, mut rem) = (num / top, num % top);
+        while q >> 32 != 0 || q * next > (rem << 32 | u[j + n - 2] as u64) {
+            q -= 1;
+            rem += top;
+            if rem >> 32 != 0 {
+                break;
+            }
+        }
+        // u -= q * v, shifted by j digits
+        let (mut borrow, mut carry) = (0i64, 0u64);
+        for i in 0..n {
+            let product = q * v[i] as u64 + carry;
+            carry = product >> 32;
+            let diff = u[i + j] as i64 - borrow - (product as u32) as i64;
+            u[i + j] = diff as u32;
+            borrow = (diff < 0) as i64;
+        }
+        let diff = u[j + n] as i64 - borrow - carry as i64;
+        u[j + n] = diff as u32;
+        if diff < 0 {
+            // The estimate was one too large, add v back
+            q -= 1;
+            let mut carry = 0;
+            for i in 0..n {
+                let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+        quotient[j] = q as u32;
+    }
+    quotient
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let abs = n.unsigned_abs();
+        Self::new(n < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add(&self.magnitude, &other.magnitude));
+        }
+        match compare(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(other.negative, sub(&other.magnitude, &self.magnitude)),
+            _ => BigInt::new(self.negative, sub(&self.magnitude, &other.magnitude)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != other.negative,
+            mul(&self.magnitude, &other.magnitude),
+        )
+    }
+}
+
+impl Div for &BigInt {
+    type Output = BigInt;
+
+    fn div(self, other: &BigInt) -> BigInt {
+        assert!(!other.is_zero(), "attempt to divide by zero");
+        BigInt::new(
+            self.negative != other.negative,
+            div(&self.magnitude, &other.magnitude),
+        )
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.magnitude, &other.magnitude),
+            (true, true) => compare(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseBigIntError(s.to_string()));
+        }
+        let mut magnitude = vec![];
+        for b in digits.bytes() {
+            magnitude = add(&mul(&magnitude, &[10]), &[(b - b'0') as u32]);
+        }
+        Ok(Self::new(negative, magnitude))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Groups of 9 decimal digits, least significant first
+        let mut groups = vec![];
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, rem) = div_small(&magnitude, 1_000_000_000);
+            groups.push(rem);
+            magnitude = quotient;
+            while magnitude.last() == Some(&0) {
+                magnitude.pop();
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", groups.pop().unwrap())?;
+        for group in groups.iter().rev() {
+            write!(f, "{group:09}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ParseBigIntError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid integer {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseBigIntError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::random::Random;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_i128() {
+        let values = [
+            0i64,
+            1,
+            -1,
+            7,
+            -13,
+            u32::MAX as i64,
+            -(u32::MAX as i64) - 1,
+            123_456_789_012,
+            i64::MAX,
+            i64::MIN,
+        ];
+        for x in values {
+            for y in values {
+                let (bx, by) = (BigInt::from(x), BigInt::from(y));
+                let (x, y) = (x as i128, y as i128);
+                assert_eq!((&bx + &by).to_string(), (x + y).to_string());
+                assert_eq!((&bx - &by).to_string(), (x - y).to_string());
+                assert_eq!((&bx * &by).to_string(), (x * y).to_string());
+                if y != 0 {
+                    assert_eq!((&bx / &by).to_string(), (x / y).to_string());
+                }
+                assert_eq!(bx.cmp(&by), x.cmp(&y));
+            }
+        }
+    }
+
+    #[test]
+    fn large() {
+        let x = big("-123456789012345678901234567890123456789");
+        let y = big("98765432109876543210987654321");
+        assert_eq!(
+            (&x * &y).to_string(),
+            "-12193263113702179522618503273374485596336229233322374638011112635269"
+        );
+        assert_eq!((&(&x * &y) / &y), x);
+        assert_eq!((&x / &y).to_string(), "-1249999988");
+        assert_eq!(big("-0"), BigInt::from(0));
+        for s in ["", "-", "abc", "12a", "+1", "--1"] {
+            assert_eq!(s.parse::<BigInt>(), Err(ParseBigIntError(s.to_string())));
+        }
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!((&BigInt::from(i64::MAX) + &BigInt::from(1)).to_i64(), None);
+    }
+
+    #[test]
+    fn division() {
+        let mut random = Random::new(0x2022_1221);
+        let mut number = |digits: usize| {
+            let mut magnitude = (0..digits)
+                .map(|_| random.next_u64() as u32)
+                .collect::<Vec<_>>();
+            // Divisors with a small top digit need the most corrections
+            if digits.is_multiple_of(3) {
+                *magnitude.last_mut().unwrap() %= 4;
+            }
+            BigInt::new(false, magnitude)
+        };
+        for digits in 2..12 {
+            for _ in 0..20 {
+                let (b, q, r) = (number(digits), number(digits + 3), number(digits - 1));
+                if r >= b {
+                    continue;
+                }
+                let a = &(&q * &b) + &r;
+                assert_eq!(&a / &b, q);
+                assert_eq!(&(&a - &r) / &b, q);
+                assert_eq!(&(&a - &r) / &q, b);
+            }
+        }
+        // Needs the estimate to be corrected after subtracting
+        let a = big("170141183420855150474555134919112130560");
+        let b = big("39614081257132168796771975169");
+        assert_eq!((&a / &b).to_string(), "4294967294");
+        let max = BigInt::new(false, vec![u32::MAX; 6]);
+        let divisor = BigInt::new(false, vec![u32::MAX; 3]);
+        assert_eq!(
+            (&max / &divisor).to_string(),
+            "79228162514264337593543950337"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{collections::hash_map::Entry, hash::Hash};
+
+use fxhash::FxHashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    // Index of the first state that is part of the cycle
+    pub start: usize,
+    // Number of steps after which states repeat
+    pub period: usize,
+}
+
+/// Remembers the step at which each state was first seen, along with a metric
+/// tracked over the simulation (e.g. the height of a tower), so the metric can be
+/// extrapolated once a state repeats.
+pub struct Detector<K> {
+    seen: FxHashMap<K, usize>,
+    metrics: Vec<i64>,
+}
+
+impl Cycle {
+    /// The step within the first repetition that is equivalent to `step`.
+    pub fn reduce(&self, step: usize) -> usize {
+        if step < self.start {
+            step
+        } else {
+            self.start + (step - self.start) % self.period
+        }
+    }
+}
+
+impl<K: Hash + Eq> Detector<K> {
+    pub fn new() -> Self {
+        Self {
+            seen: FxHashMap::default(),
+            metrics: vec![],
+        }
+    }
+
+    /// Records the state reached at the next step, returning the cycle if the
+    /// state was already seen.
+    pub fn record(&mut self, key: K, metric: i64) -> Option<Cycle> {
+        let step = self.metrics.len();
+        self.metrics.push(metric);
+        match self.seen.entry(key) {
+            Entry::Occupied(e) => Some(Cycle {
+                start: *e.get(),
+                period: step - e.get(),
+            }),
+            Entry::Vacant(e) => {
+                e.insert(step);
+                None
+            }
+        }
+    }
+
+    /// Value of the metric at any step, assuming it changes by the same amount
+    /// over every period of `cycle`.
+    pub fn extrapolate(&self, cycle: Cycle, step: usize) -> i64 {
+        if step < self.metrics.len() {
+            return self.metrics[step];
+        }
+        let gain = self.metrics[cycle.start + cycle.period] - self.metrics[cycle.start];
+        let periods = (step - cycle.start) / cycle.period;
+        self.metrics[cycle.reduce(step)] + gain * periods as i64
+    }
+}
+
+impl<K: Hash + Eq> Default for Detector<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Floyd's tortoise and hare, for states that are cheap to compare but
+/// expensive to hash or store.
+pub fn floyd<T: Clone + PartialEq>(initial: T, mut next: impl FnMut(&T) -> T) -> Cycle {
+    let mut tortoise = next(&initial);
+    let mut hare = next(&tortoise);
+    while tortoise != hare {
+        tortoise = next(&tortoise);
+        let half = next(&hare);
+        hare = next(&half);
+    }
+
+    let mut start = 0;
+    tortoise = initial;
+    while tortoise != hare {
+        tortoise = next(&tortoise);
+        hare = next(&hare);
+        start += 1;
+    }
+
+    let mut period = 1;
+    hare = next(&tortoise);
+    while tortoise != hare {
+        hare = next(&hare);
+        period += 1;
+    }
+    Cycle { start, period }
+}
+
+/// Brent's algorithm, same as [`floyd`] but with fewer calls to `next`.
+pub fn brent<T: Clone + PartialEq>(initial: T, mut next: impl FnMut(&T) -> T) -> Cycle {
+    let (mut power, mut period) = (1, 1);
+    let mut tortoise = initial.clone();
+    let mut hare = next(&initial);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = next(&hare);
+        period += 1;
+    }
+
+    tortoise = initial.clone();
+    hare = initial;
+    for _ in 0..period {
+        hare = next(&hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        tortoise = next(&tortoise);
+        hare = next(&hare);
+        start += 1;
+    }
+    Cycle { start, period }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(x: &u32) -> u32 {
+        (x * x + 1) % 101
+    }
+
+    #[test]
+    fn floyd_brent() {
+        let expected = Cycle {
+            start: 12,
+            period: 9,
+        };
+        assert_eq!(floyd(3, next), expected);
+        assert_eq!(brent(3, next), expected);
+    }
+
+    #[test]
+    fn detector_extrapolation() {
+        let mut detector = Detector::new();
+        let (mut x, mut sum) = (3, 0);
+        let cycle = (0..)
+            .find_map(|_| {
+                let cycle = detector.record(x, sum);
+                sum += x as i64;
+                x = next(&x);
+                cycle
+            })
+            .unwrap();
+        assert_eq!(cycle.start, 12);
+        assert_eq!(cycle.period, 9);
+
+        let brute_force: i64 = std::iter::successors(Some(3), |x| Some(next(x)))
+            .take(1000)
+            .map(|x| x as i64)
+            .sum();
+        assert_eq!(detector.extrapolate(cycle, 1000), brute_force);
+    }
+}
@@ -0,0 +1,144 @@
+pub type Color = [u8; 3];
+
+/// Plain RGB image, for visualizing simulations without pulling in an
+/// image library.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0; 3]; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Paints a `scale * scale` square, for images where each cell of a grid
+    /// spans several pixels.
+    pub fn fill_cell(&mut self, x: usize, y: usize, scale: usize, color: Color) {
+        for py in y * scale..(y + 1) * scale {
+            for px in x * scale..(x + 1) * scale {
+                self.set(px, py, color);
+            }
+        }
+    }
+
+    /// Binary (P6) PPM encoding.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend(self.pixels.iter().flatten());
+        out
+    }
+
+    /// PNG encoding. The image data is not compressed, it is stored as-is in
+    /// deflate blocks.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = vec![];
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8-bit RGB, default compression and filter, no interlacing
+        header.extend([8, 2, 0, 0, 0]);
+        write_chunk(&mut out, b"IHDR", &header);
+
+        let mut raw = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for row in self.pixels.chunks(self.width.max(1)) {
+            // No filter
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+        let mut zlib = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend([1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            let len = block.len() as u16;
+            zlib.push(blocks.peek().is_none() as u8);
+            zlib.extend(len.to_le_bytes());
+            zlib.extend((!len).to_le_bytes());
+            zlib.extend(block);
+        }
+        zlib.extend(adler32(&raw).to_be_bytes());
+        write_chunk(&mut out, b"IDAT", &zlib);
+
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(kind.iter().chain(data));
+    out.extend(crc.to_be_bytes());
+}
+
+fn crc32<'a>(data: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn encode() {
+        let mut image = Image::new(2, 1);
+        image.set(1, 0, [255, 0, 10]);
+        assert_eq!(image.to_ppm(), b"P6\n2 1\n255\n\0\0\0\xff\0\x0a");
+
+        let png = image.to_png();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x02\0\0\0\x01"));
+        assert!(png.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+    }
+}
@@ -0,0 +1,27 @@
+/// Xorshift64 generator, for tests and benches that need large inputs that are
+/// the same on every run.
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift needs a non-zero seed");
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..max`.
+    pub fn below(&mut self, max: u64) -> u64 {
+        self.next_u64() % max
+    }
+
+    /// A number in `min..=max`.
+    pub fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + self.below((max - min + 1) as u64) as i64
+    }
+}